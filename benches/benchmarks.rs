@@ -3,7 +3,7 @@
 extern crate test;
 extern crate dds;
 
-use dds::Dds;
+use dds::{DecodeOptions, Dds};
 use std::fs::File;
 use std::io::Cursor;
 use test::Bencher;
@@ -24,4 +24,41 @@ mod tests {
       Dds::decode(&mut Cursor::new(buf.clone())).unwrap()
     });
   }
+
+  #[bench]
+  fn bench_decode_dxt1(b: &mut Bencher) {
+    let mut buf = Vec::new();
+    let mut file = File::open("./samples/dxt1.dds").expect("Couldn't find file!");
+
+    file.read_to_end(&mut buf).unwrap();
+
+    b.iter(|| {
+      Dds::decode(&mut Cursor::new(buf.clone())).unwrap()
+    });
+  }
+
+  #[bench]
+  fn bench_decode_dxt5(b: &mut Bencher) {
+    let mut buf = Vec::new();
+    let mut file = File::open("./samples/dxt5.dds").expect("Couldn't find file!");
+
+    file.read_to_end(&mut buf).unwrap();
+
+    b.iter(|| {
+      Dds::decode(&mut Cursor::new(buf.clone())).unwrap()
+    });
+  }
+
+  #[bench]
+  fn bench_decode_dxt5_ignore_alpha(b: &mut Bencher) {
+    let mut buf = Vec::new();
+    let mut file = File::open("./samples/dxt5.dds").expect("Couldn't find file!");
+
+    file.read_to_end(&mut buf).unwrap();
+
+    let options = DecodeOptions { ignore_alpha: true, ..DecodeOptions::default() };
+    b.iter(|| {
+      Dds::decode_with(&mut Cursor::new(buf.clone()), &options).unwrap()
+    });
+  }
 }
@@ -0,0 +1,61 @@
+use image::{imageops, RgbaImage};
+
+/// A rectangular region of an image, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32
+}
+
+impl Rect {
+  /// Returns true if the two rectangles share any pixels.
+  pub fn intersects(&self, other: &Rect) -> bool {
+    self.x < other.x + other.width && other.x < self.x + self.width &&
+    self.y < other.y + other.height && other.y < self.y + self.height
+  }
+}
+
+/// Packs a set of images into a single atlas with power-of-two dimensions, leaving `padding`
+/// pixels between neighbouring images. Returns the atlas, along with the placement of each
+/// source image in the same order as `images`.
+///
+/// Images are placed on shelves, tallest first, which works well for sets of similarly sized images.
+pub fn pack_atlas(images: &[RgbaImage], padding: u32) -> (RgbaImage, Vec<Rect>) {
+  let max_width = images.iter().map(|image| image.width()).max().unwrap_or(0);
+  let area: u64 = images.iter()
+    .map(|image| (image.width() + padding) as u64 * (image.height() + padding) as u64)
+    .sum();
+
+  // Aim for a roughly square atlas, but it must be at least as wide as the widest image
+  let width = ((area as f64).sqrt().ceil() as u32).max(max_width).max(1).next_power_of_two();
+
+  let mut order = (0..images.len()).collect::<Vec<_>>();
+  order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+  let mut rects = vec![Rect { x: 0, y: 0, width: 0, height: 0 }; images.len()];
+  let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+  for i in order {
+    let (w, h) = images[i].dimensions();
+
+    // Start a new shelf once this one is full
+    if x > 0 && x + w > width {
+      y += shelf_height + padding;
+      x = 0;
+      shelf_height = 0;
+    };
+
+    rects[i] = Rect { x, y, width: w, height: h };
+    x += w + padding;
+    shelf_height = shelf_height.max(h);
+  };
+
+  let height = (y + shelf_height).max(1).next_power_of_two();
+  let mut atlas = RgbaImage::new(width, height);
+  for (image, rect) in images.iter().zip(rects.iter()) {
+    imageops::replace(&mut atlas, image, rect.x, rect.y);
+  };
+
+  (atlas, rects)
+}
@@ -0,0 +1,79 @@
+// Reads fields of any width up to 64 bits from a buffer, starting from the least significant
+// bit of the first byte. Fields may span byte boundaries, as in the modes of BC6H and BC7 blocks.
+pub struct BitReader<'a> {
+  bytes: &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  pub fn new(bytes: &'a [u8]) -> BitReader<'a> {
+    BitReader { bytes, position: 0 }
+  }
+
+  // Reads the next `bits` bits, the first of them becoming the least significant bit of the result.
+  // Bits past the end of the buffer read as zero.
+  pub fn read(&mut self, bits: u8) -> u64 {
+    assert!(bits <= 64, "cannot read more than 64 bits at once");
+
+    let mut value = 0;
+    let mut filled = 0;
+    while filled < bits as usize {
+      let byte = self.bytes.get(self.position / 8).copied().unwrap_or(0) as u64;
+      let offset = self.position % 8;
+      let take = (8 - offset).min(bits as usize - filled);
+      value |= ((byte >> offset) & ((1 << take) - 1)) << filled;
+      filled += take;
+      self.position += take;
+    };
+
+    value
+  }
+
+  // Mode and flag bits of BC6H and BC7 blocks are read one at a time
+  #[allow(dead_code)]
+  pub fn read_bit(&mut self) -> bool {
+    self.read(1) != 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::BitReader;
+
+  #[test]
+  fn test_read_across_bytes() {
+    let bytes = [
+      0xAC, 0x3C, 0xF0, 0x55, 0xAA, 0x0F, 0x81, 0x7E, 0x12, 0x34,
+      0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, 0xC3, 0xA5, 0x5A, 0x99
+    ];
+    let expected = [
+      0x0, 0x2, 0x5, 0x2, 0xF, 0x20, 0x2F, 0xA5, 0xFA,
+      0x8, 0x4FD, 0xD04, 0x158, 0x134F, 0x6F5E, 0xC3F0, 0x15AA5
+    ];
+
+    // Fields of widths 1 to 17 in turn, most of them spanning a byte boundary
+    let mut reader = BitReader::new(&bytes);
+    for (bits, &value) in (1..=17).zip(expected.iter()) {
+      assert_eq!(reader.read(bits), value, "field of {} bits", bits);
+    };
+
+    // 153 bits have been read, leaving the top 7 bits of the last byte
+    assert_eq!(reader.read(7), 0x99 >> 1);
+    assert_eq!(reader.read(8), 0);
+  }
+
+  #[test]
+  fn test_read_bit() {
+    let mut reader = BitReader::new(&[0b1000_0101, 0b0000_0001]);
+    let bits = (0..10).map(|_| reader.read_bit()).collect::<Vec<_>>();
+    assert_eq!(bits, [true, false, true, false, false, false, false, true, true, false]);
+  }
+
+  #[test]
+  fn test_read_wide() {
+    let bytes = 0x0123_4567_89AB_CDEFu64.to_le_bytes();
+    let mut reader = BitReader::new(&bytes);
+    assert_eq!(reader.read(4), 0xF);
+    assert_eq!(reader.read(64), 0x0012_3456_789A_BCDE);
+  }
+}
@@ -0,0 +1,170 @@
+use image::RgbaImage;
+
+use crate::format::Layout;
+use crate::{EdgePad, EncodeOptions};
+
+// Gathers the 4x4 block of texels whose top-left corner is at (`x`, `y`), filling
+// any texels past the edges of the image according to `edge_pad`
+fn gather_block(image: &RgbaImage, x: u32, y: u32, edge_pad: EdgePad) -> [[u8; 4]; 16] {
+  let (width, height) = image.dimensions();
+  let pad = |i: u32, size: u32| match edge_pad {
+    _ if i < size => Some(i),
+    EdgePad::Clamp => Some(size - 1),
+    EdgePad::Mirror => Some((2 * (size - 1)).saturating_sub(i)),
+    EdgePad::Zero => None
+  };
+
+  let mut block = [[0; 4]; 16];
+  for (i, texel) in block.iter_mut().enumerate() {
+    let (tx, ty) = (x + i as u32 % 4, y + i as u32 / 4);
+    if let (Some(tx), Some(ty)) = (pad(tx, width), pad(ty, height)) {
+      *texel = image.get_pixel(tx, ty).0;
+    };
+  };
+
+  block
+}
+
+fn pack_565(color: [u8; 3]) -> u16 {
+  let [r, g, b] = color.map(|c| c as u32);
+  (((r * 31 + 127) / 255) << 11 | ((g * 63 + 127) / 255) << 5 | ((b * 31 + 127) / 255)) as u16
+}
+
+// Inflates a 5:6:5 color the same way the decoder does
+fn unpack_565(color: u16) -> [u32; 3] {
+  let color = color as u32;
+  [((color >> 11) & 0x1F) * 255 / 31, ((color >> 5) & 0x3F) * 255 / 63, (color & 0x1F) * 255 / 31]
+}
+
+fn distance(a: [u32; 3], b: [u8; 4]) -> u32 {
+  (0..3).map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32).sum()
+}
+
+// Fits both endpoints to the bounding box of the block's colors, inset slightly to
+// reduce the error near the middle of the range, then picks the closest palette entry for
+// each texel. Uses the four color mode, where `color0` is kept above `color1`, unless
+// `alpha_threshold` is given and some texel's alpha is below it. Those texels are then made
+// transparent with the three color mode instead, where `color0` is kept at or below `color1`.
+fn encode_color_block(block: &[[u8; 4]; 16], alpha_threshold: Option<u8>, out: &mut Vec<u8>) {
+  let transparent = |texel: &[u8; 4]| alpha_threshold.is_some_and(|threshold| texel[3] < threshold);
+  let punch_through = block.iter().any(transparent);
+
+  let mut min = [255u8; 3];
+  let mut max = [0u8; 3];
+  for texel in block.iter().filter(|texel| !transparent(texel)) {
+    for c in 0..3 {
+      min[c] = min[c].min(texel[c]);
+      max[c] = max[c].max(texel[c]);
+    };
+  };
+
+  // A fully transparent block has no colors to fit
+  if min.iter().zip(max.iter()).any(|(min, max)| min > max) {
+    (min, max) = ([0; 3], [0; 3]);
+  };
+
+  for c in 0..3 {
+    let inset = (max[c] - min[c]) / 16;
+    min[c] += inset;
+    max[c] -= inset;
+  };
+
+  let (color0, color1) = match (pack_565(max), pack_565(min)) {
+    (color0, color1) if (color0 < color1) != punch_through => (color1, color0),
+    colors => colors
+  };
+
+  let c0 = unpack_565(color0);
+  let c1 = unpack_565(color1);
+  let palette = match punch_through {
+    false => [
+      c0,
+      c1,
+      [0, 1, 2].map(|c| (2 * c0[c] + c1[c]) / 3),
+      [0, 1, 2].map(|c| (c0[c] + 2 * c1[c]) / 3)
+    ],
+    true => [c0, c1, [0, 1, 2].map(|c| (c0[c] + c1[c]) / 2), c0]
+  };
+
+  let mut codes = [0u8; 4];
+  for (i, texel) in block.iter().enumerate() {
+    let index = match (transparent(texel), punch_through) {
+      (true, _) => 3,
+      (false, false) if color0 == color1 => 0,
+      (false, false) => (0..4).min_by_key(|&index| distance(palette[index], *texel)).unwrap_or(0),
+      (false, true) => (0..3).min_by_key(|&index| distance(palette[index], *texel)).unwrap_or(0)
+    };
+    codes[i / 4] |= (index as u8) << (2 * (i % 4));
+  };
+
+  out.extend_from_slice(&color0.to_le_bytes());
+  out.extend_from_slice(&color1.to_le_bytes());
+  out.extend_from_slice(&codes);
+}
+
+// Stores the alpha of each texel explicitly, rounded to 4 bits
+fn encode_alpha_block_dxt3(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+  for pair in block.chunks(2) {
+    let low = (pair[0][3] as u32 + 8) / 17;
+    let high = (pair[1][3] as u32 + 8) / 17;
+    out.push((high << 4 | low) as u8);
+  };
+}
+
+// Uses the eight value mode between the smallest and largest alpha in the block
+fn encode_alpha_block_dxt5(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+  let alpha0 = block.iter().map(|texel| texel[3] as u32).max().unwrap_or(255);
+  let alpha1 = block.iter().map(|texel| texel[3] as u32).min().unwrap_or(255);
+  let palette = [
+    alpha0,
+    alpha1,
+    (6 * alpha0 + alpha1) / 7,
+    (5 * alpha0 + 2 * alpha1) / 7,
+    (4 * alpha0 + 3 * alpha1) / 7,
+    (3 * alpha0 + 4 * alpha1) / 7,
+    (2 * alpha0 + 5 * alpha1) / 7,
+    (alpha0 + 6 * alpha1) / 7
+  ];
+
+  let mut codes = 0u64;
+  if alpha0 != alpha1 {
+    for (i, texel) in block.iter().enumerate() {
+      let alpha = texel[3] as u32;
+      let index = (0..8).min_by_key(|&index| palette[index].abs_diff(alpha)).unwrap_or(0);
+      codes |= (index as u64) << (3 * i);
+    };
+  };
+
+  out.push(alpha0 as u8);
+  out.push(alpha1 as u8);
+  out.extend_from_slice(&codes.to_le_bytes()[..6]);
+}
+
+// Compresses an image into a series of DXT1, DXT3 or DXT5 blocks, in scanline order.
+// DXT1 with alpha gets punch-through alpha, following the options' alpha threshold.
+pub fn encode_image_dxt(image: &RgbaImage, layout: Layout, options: &EncodeOptions) -> Vec<u8> {
+  let (width, height) = image.dimensions();
+  let chunk_size = layout.block_bytes().expect("layout is not block compressed");
+  let mut buf = Vec::with_capacity(width.div_ceil(4) as usize * height.div_ceil(4) as usize * chunk_size);
+  for y in (0..height).step_by(4) {
+    for x in (0..width).step_by(4) {
+      let block = gather_block(image, x, y, options.edge_pad);
+      let alpha_threshold = match layout {
+        Layout::Dxt1 { alpha } => alpha.then_some(options.alpha_threshold),
+        Layout::Dxt3 => {
+          encode_alpha_block_dxt3(&block, &mut buf);
+          None
+        },
+        Layout::Dxt5 => {
+          encode_alpha_block_dxt5(&block, &mut buf);
+          None
+        },
+        _ => unreachable!("This function cannot handle `{:?}` images", layout)
+      };
+
+      encode_color_block(&block, alpha_threshold, &mut buf);
+    };
+  };
+
+  buf
+}
@@ -0,0 +1,162 @@
+use image::RgbaImage;
+
+use crate::flags::*;
+use crate::format::decode_layers;
+use crate::{DecodeError, DecodeOptions, Dds, Header};
+
+use std::io::{Read, Seek, SeekFrom};
+
+/// One of the six faces of a cubemap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+  PositiveX,
+  NegativeX,
+  PositiveY,
+  NegativeY,
+  PositiveZ,
+  NegativeZ
+}
+
+impl CubeFace {
+  /// Every face, in the order they're stored in the file.
+  pub const ALL: [CubeFace; 6] = [
+    CubeFace::PositiveX,
+    CubeFace::NegativeX,
+    CubeFace::PositiveY,
+    CubeFace::NegativeY,
+    CubeFace::PositiveZ,
+    CubeFace::NegativeZ
+  ];
+
+  /// The `DDSCAPS2_CUBEMAP_*` flag marking this face as present.
+  pub fn flag(self) -> u32 {
+    match self {
+      CubeFace::PositiveX => DDSCAPS2_CUBEMAP_POSITIVEX,
+      CubeFace::NegativeX => DDSCAPS2_CUBEMAP_NEGATIVEX,
+      CubeFace::PositiveY => DDSCAPS2_CUBEMAP_POSITIVEY,
+      CubeFace::NegativeY => DDSCAPS2_CUBEMAP_NEGATIVEY,
+      CubeFace::PositiveZ => DDSCAPS2_CUBEMAP_POSITIVEZ,
+      CubeFace::NegativeZ => DDSCAPS2_CUBEMAP_NEGATIVEZ
+    }
+  }
+
+  /// The position of this face in [`CubeFace::ALL`].
+  pub fn index(self) -> usize {
+    self as usize
+  }
+}
+
+/// Represents a parsed cubemap DDS file, with the mipmap chain of each face.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cubemap {
+  /// The parsed DDS header
+  pub header: Header,
+  /// Mipmap layers of each face, ordered as in [`CubeFace::ALL`]
+  pub faces: [Vec<RgbaImage>; 6]
+}
+
+impl Cubemap {
+  /// Decodes a cubemap into the mipmap chains of its six faces.
+  /// Fails with [`DecodeError::NotCubemap`] if the file isn't a cubemap with every face present.
+  pub fn decode<R: Read>(reader: R) -> Result<Cubemap, DecodeError> {
+    Cubemap::decode_with(reader, &DecodeOptions::default())
+  }
+
+  /// Decodes a cubemap into the mipmap chains of its six faces, using the given options.
+  pub fn decode_with<R: Read>(reader: R, options: &DecodeOptions) -> Result<Cubemap, DecodeError> {
+    let Dds { header, mut layers } = Dds::decode_with(reader, options)?;
+    check_all_faces(&header)?;
+
+    // The layers are the mipmap chain of each face in turn, so split them back up from the end
+    let levels = layers.len() / 6;
+    let mut faces: [Vec<RgbaImage>; 6] = Default::default();
+    for face in faces.iter_mut().rev() {
+      *face = layers.split_off(layers.len() - levels);
+    };
+
+    Ok(Cubemap { header, faces })
+  }
+
+  /// Returns the mipmap chain of a single face.
+  pub fn face(&self, face: CubeFace) -> &[RgbaImage] {
+    &self.faces[face.index()]
+  }
+
+  /// Splits the cubemap into six standalone 2D textures, ordered as in [`CubeFace::ALL`], each keeping
+  /// the mipmap chain of its face. Fails with [`DecodeError::NotCubemap`] if the header isn't a cubemap.
+  pub fn into_faces(self) -> Result<[Dds; 6], DecodeError> {
+    check_all_faces(&self.header)?;
+
+    let Cubemap { header, faces } = self;
+    Ok(faces.map(|layers| {
+      let header = Header {
+        mipmap_count: layers.len() as u32,
+        ..header.level_header(0)
+      };
+
+      Dds { header, layers }
+    }))
+  }
+}
+
+// Cubemaps are only usable as such when none of their faces are missing
+fn check_all_faces(header: &Header) -> Result<(), DecodeError> {
+  match header.is_cubemap() && header.caps2 & DDSCAPS2_CUBEMAP_ALLFACES == DDSCAPS2_CUBEMAP_ALLFACES {
+    true => Ok(()),
+    false => Err(DecodeError::NotCubemap)
+  }
+}
+
+/// Decodes individual cubemap faces on demand, seeking straight to each requested surface
+/// instead of decoding the whole file like [`Cubemap::decode`] does.
+#[derive(Debug)]
+pub struct CubemapDecoder<R> {
+  reader: R,
+  /// The parsed DDS header
+  pub header: Header,
+  options: DecodeOptions,
+  // Where the payload starts in the reader
+  payload_start: u64,
+  // The offset and size of every surface, in file order
+  layer_offsets: Vec<(usize, usize)>
+}
+
+impl<R: Read + Seek> CubemapDecoder<R> {
+  /// Reads the header of a cubemap, leaving the faces to be decoded by [`CubemapDecoder::face`].
+  /// Fails with [`DecodeError::NotCubemap`] if the file isn't a cubemap with every face present.
+  pub fn new(reader: R) -> Result<CubemapDecoder<R>, DecodeError> {
+    CubemapDecoder::new_with(reader, DecodeOptions::default())
+  }
+
+  /// Reads the header of a cubemap, decoding faces with the given options.
+  pub fn new_with(mut reader: R, options: DecodeOptions) -> Result<CubemapDecoder<R>, DecodeError> {
+    let header = Header::decode_with(&mut reader, &options)?;
+    check_all_faces(&header)?;
+
+    let payload_start = reader.stream_position()?;
+    let layer_offsets = header.layer_offsets()?;
+    Ok(CubemapDecoder { reader, header, options, payload_start, layer_offsets })
+  }
+
+  /// Decodes a single mipmap level of a single face.
+  pub fn face(&mut self, face: CubeFace, level: usize) -> Result<RgbaImage, DecodeError> {
+    let count = self.header.mipmap_levels() as usize;
+    if level >= count {
+      return Err(DecodeError::MipmapLevelOutOfRange { level, count });
+    };
+
+    let (offset, size) = self.layer_offsets[face.index() * count + level];
+    self.reader.seek(SeekFrom::Start(self.payload_start + offset as u64))?;
+
+    let mut buf = vec![0; size];
+    self.reader.read_exact(&mut buf)?;
+
+    let mut layers = decode_layers(&self.header.level_header(level), &buf, &self.options)?;
+    Ok(layers.remove(0))
+  }
+
+  /// Consumes the decoder, returning the inner reader.
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+}
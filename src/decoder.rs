@@ -0,0 +1,58 @@
+use image::error::{DecodingError, ImageError, ImageFormatHint, ImageResult};
+use image::{ColorType, ImageDecoder, ImageFormat};
+
+use crate::format::decode_layers;
+use crate::{DecodeError, DecodeOptions, Header};
+
+use std::io::{Cursor, Read};
+
+/// Adapts a DDS file to the `image` crate's [`ImageDecoder`] trait, so it can be loaded
+/// like any other format, for example with `DynamicImage::from_decoder`.
+/// Only the main image is decoded, always as `Rgba8`.
+#[derive(Debug)]
+pub struct DdsImageDecoder<R> {
+  reader: R,
+  header: Header
+}
+
+impl<R: Read> DdsImageDecoder<R> {
+  /// Reads the header of a DDS file, leaving the pixels to be decoded through the trait.
+  pub fn new(mut reader: R) -> Result<DdsImageDecoder<R>, DecodeError> {
+    let header = Header::decode(&mut reader)?;
+    Ok(DdsImageDecoder { reader, header })
+  }
+
+  /// The parsed DDS header.
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+}
+
+// Keeps IO errors as they are, and wraps everything else as a DDS decoding error
+fn into_image_error(err: DecodeError) -> ImageError {
+  match err {
+    DecodeError::Io(err) => ImageError::IoError(err),
+    err => ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Dds), err))
+  }
+}
+
+impl<'a, R: Read + 'a> ImageDecoder<'a> for DdsImageDecoder<R> {
+  type Reader = Cursor<Vec<u8>>;
+
+  fn dimensions(&self) -> (u32, u32) {
+    (self.header.width, self.header.height)
+  }
+
+  fn color_type(&self) -> ColorType {
+    ColorType::Rgba8
+  }
+
+  fn into_reader(mut self) -> ImageResult<Cursor<Vec<u8>>> {
+    let mut buf = Vec::new();
+    self.reader.read_to_end(&mut buf)?;
+
+    let options = DecodeOptions { max_level: Some(0), ..Default::default() };
+    let mut layers = decode_layers(&self.header, &buf, &options).map_err(into_image_error)?;
+    Ok(Cursor::new(layers.remove(0).into_raw()))
+  }
+}
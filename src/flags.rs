@@ -0,0 +1,51 @@
+//! Flag constants used by the bitfields of [`RawHeader`](crate::RawHeader) and
+//! [`RawPixelFormat`](crate::RawPixelFormat).
+//!
+//! See here for more information:
+//! <https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-header>
+
+// `RawHeader::flags`
+pub const DDSD_CAPS: u32 = 0x1;
+pub const DDSD_HEIGHT: u32 = 0x2;
+pub const DDSD_WIDTH: u32 = 0x4;
+pub const DDSD_PITCH: u32 = 0x8;
+pub const DDSD_PIXELFORMAT: u32 = 0x1000;
+pub const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+pub const DDSD_LINEARSIZE: u32 = 0x80000;
+pub const DDSD_DEPTH: u32 = 0x800000;
+
+// `RawPixelFormat::flags`
+pub const DDPF_ALPHAPIXELS: u32 = 0x1;
+pub const DDPF_ALPHA: u32 = 0x2;
+pub const DDPF_FOURCC: u32 = 0x4;
+pub const DDPF_RGB: u32 = 0x40;
+pub const DDPF_YUV: u32 = 0x200;
+pub const DDPF_LUMINANCE: u32 = 0x20000;
+
+// `RawHeader::caps`
+pub const DDSCAPS_COMPLEX: u32 = 0x8;
+pub const DDSCAPS_TEXTURE: u32 = 0x1000;
+pub const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+// `RawHeader::caps2`
+pub const DDSCAPS2_CUBEMAP: u32 = 0x200;
+pub const DDSCAPS2_CUBEMAP_POSITIVEX: u32 = 0x400;
+pub const DDSCAPS2_CUBEMAP_NEGATIVEX: u32 = 0x800;
+pub const DDSCAPS2_CUBEMAP_POSITIVEY: u32 = 0x1000;
+pub const DDSCAPS2_CUBEMAP_NEGATIVEY: u32 = 0x2000;
+pub const DDSCAPS2_CUBEMAP_POSITIVEZ: u32 = 0x4000;
+pub const DDSCAPS2_CUBEMAP_NEGATIVEZ: u32 = 0x8000;
+pub const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+pub const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// `HeaderDx10::resource_dimension`
+pub const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+pub const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+pub const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+
+// `HeaderDx10::misc_flag`
+pub const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+// `HeaderDx10::misc_flags2`
+pub const DDS_ALPHA_MODE_MASK: u32 = 0x7;
+pub const DDS_ALPHA_MODE_PREMULTIPLIED: u32 = 0x2;
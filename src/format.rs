@@ -1,42 +1,587 @@
-use image::RgbaImage;
+use image::{GrayImage, ImageBuffer, Luma, Primitive, Rgba, RgbaImage};
 
-use crate::{Compression, Header, PixelFormat};
+use crate::bits::BitReader;
+use crate::{Channel, ChannelOrder, Compression, DecodeError, DecodedImage, DecodedPixel, DecodeOptions, DxgiFormat, Header, NativeDds, PixelFormat, TileMode};
+
+use std::borrow::Cow;
+
+// Describes how the payload of a file is laid out, and which decoder handles it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+  // Uncompressed pixels, described by the header's pixel size and channel masks
+  Uncompressed,
+  // Uncompressed pixels made up of 1-4 floating point channels, either 16 or 32 bits wide
+  Float { channels: usize, half: bool },
+  // Uncompressed pixels made up of 1-4 signed normalized 8-bit channels
+  Snorm { channels: usize },
+  // Uncompressed pixels made up of 1-4 normalized 16-bit channels, either signed or unsigned
+  Int16 { channels: usize, signed: bool },
+  Dxt1 { alpha: bool },
+  Dxt3,
+  Dxt5,
+  // DXT5 blocks holding a tangent space normal map, aka BC3n or DXT5nm. X is stored in alpha and
+  // Y in green for their extra precision, while red and blue go unused, so Z is rebuilt instead.
+  Dxt5nm,
+  // Single channel blocks, encoded like the alpha of DXT5 blocks
+  Bc4,
+  // Two channel blocks, made up of a BC4 block for red followed by one for green
+  Bc5,
+  // Packed YUV 4:2:2, where each pair of pixels shares a single chroma sample. The four bytes
+  // of each pair are stored as Y0 U Y1 V for YUY2, and as U Y0 V Y1 for UYVY.
+  Yuv422 { uyvy: bool }
+}
+
+impl Layout {
+  pub fn from_header(header: &Header) -> Result<Layout, DecodeError> {
+    match header.compression {
+      Compression::None => Ok(Layout::Uncompressed),
+      Compression::DXT1 => Ok(Layout::Dxt1 { alpha: header.pixel_format != PixelFormat::Unknown }),
+      Compression::DXT1A => Ok(Layout::Dxt1 { alpha: true }),
+      Compression::DXT2 | Compression::DXT3 => Ok(Layout::Dxt3),
+      Compression::DXT4 | Compression::DXT5 => Ok(Layout::Dxt5),
+      Compression::DX10 => match header.dx10 {
+        Some(dx10) => Layout::from_dxgi_format(dx10.dxgi_format),
+        None => Err(DecodeError::UnsupportedCompression(Compression::DX10))
+      },
+      Compression::Other(four_cc) => match &four_cc {
+        b"ATI1" | b"BC4U" => Ok(Layout::Bc4),
+        b"ATI2" | b"BC5U" => Ok(Layout::Bc5),
+        b"RXGB" => Ok(Layout::Dxt5nm),
+        b"YUY2" => Ok(Layout::Yuv422 { uyvy: false }),
+        b"UYVY" => Ok(Layout::Yuv422 { uyvy: true }),
+        _ => Layout::from_d3d_format(u32::from_le_bytes(four_cc))
+          .ok_or(DecodeError::UnsupportedCompression(Compression::Other(four_cc)))
+      }
+    }
+  }
+
+  // Older writers store a numeric `D3DFORMAT` value in the four-cc rather than ASCII characters
+  fn from_d3d_format(format: u32) -> Option<Layout> {
+    match format {
+      // D3DFMT_A16B16G16R16
+      36 => Some(Layout::Int16 { channels: 4, signed: false }),
+      // D3DFMT_Q16W16V16U16
+      110 => Some(Layout::Int16 { channels: 4, signed: true }),
+      // D3DFMT_R16F, D3DFMT_G16R16F and D3DFMT_A16B16G16R16F
+      111 => Some(Layout::Float { channels: 1, half: true }),
+      112 => Some(Layout::Float { channels: 2, half: true }),
+      113 => Some(Layout::Float { channels: 4, half: true }),
+      // D3DFMT_R32F, D3DFMT_G32R32F and D3DFMT_A32B32G32R32F
+      114 => Some(Layout::Float { channels: 1, half: false }),
+      115 => Some(Layout::Float { channels: 2, half: false }),
+      116 => Some(Layout::Float { channels: 4, half: false }),
+      _ => None
+    }
+  }
+
+  // Returns the number of bytes in each 4x4 block of block compressed layouts
+  pub fn block_bytes(self) -> Option<usize> {
+    match self {
+      // DXT1 compression uses 64 bits per 16 pixels, while DXT2-5 use 128 bits
+      Layout::Dxt1 { .. } | Layout::Bc4 => Some(8),
+      Layout::Dxt3 | Layout::Dxt5 | Layout::Dxt5nm | Layout::Bc5 => Some(16),
+      _ => None
+    }
+  }
+
+  // Returns the number of bytes each mipmap layer occupies in the payload, including every slice of volume textures
+  pub fn layer_byte_sizes(self, header: &Header) -> Vec<usize> {
+    header.get_layer_sizes().into_iter().enumerate()
+      .map(|(i, (h, w))| header.layer_depth(i) * match self {
+        Layout::Uncompressed => h * layer_pitch(header, i, w * header.pixel_bytes),
+        Layout::Float { channels, half } => {
+          let channel_bytes = if half { 2 } else { 4 };
+          h * layer_pitch(header, i, w * channels * channel_bytes)
+        },
+        Layout::Snorm { channels } => h * layer_pitch(header, i, w * channels),
+        Layout::Int16 { channels, .. } => h * layer_pitch(header, i, w * channels * 2),
+        // Odd widths are padded out to a whole pair of pixels
+        Layout::Yuv422 { .. } => h * layer_pitch(header, i, w.div_ceil(2) * 4),
+        // Blocks cover 4x4 texels, and partial blocks at the edges are stored in full
+        Layout::Dxt1 { .. } | Layout::Dxt3 | Layout::Dxt5 | Layout::Dxt5nm | Layout::Bc4 | Layout::Bc5 => {
+          let row_size = w.div_ceil(4) * self.block_bytes().unwrap_or(0);
+          h.div_ceil(4) * block_row_pitch(header, i, row_size, h.div_ceil(4))
+        }
+      })
+      .collect()
+  }
+
+  fn from_dxgi_format(format: DxgiFormat) -> Result<Layout, DecodeError> {
+    // Typeless formats decode exactly like their UNORM interpretation
+    match format.typeless_to_unorm() {
+      DxgiFormat::BC1_UNORM | DxgiFormat::BC1_UNORM_SRGB => Ok(Layout::Dxt1 { alpha: true }),
+      DxgiFormat::BC2_UNORM | DxgiFormat::BC2_UNORM_SRGB => Ok(Layout::Dxt3),
+      DxgiFormat::BC3_UNORM | DxgiFormat::BC3_UNORM_SRGB => Ok(Layout::Dxt5),
+      DxgiFormat::BC4_UNORM => Ok(Layout::Bc4),
+      DxgiFormat::BC5_UNORM => Ok(Layout::Bc5),
+      DxgiFormat::YUY2 => Ok(Layout::Yuv422 { uyvy: false }),
+      DxgiFormat::R16G16_FLOAT => Ok(Layout::Float { channels: 2, half: true }),
+      DxgiFormat::R16G16B16A16_UNORM => Ok(Layout::Int16 { channels: 4, signed: false }),
+      DxgiFormat::R16G16B16A16_SNORM => Ok(Layout::Int16 { channels: 4, signed: true }),
+      DxgiFormat::R8G8B8A8_SNORM => Ok(Layout::Snorm { channels: 4 }),
+      DxgiFormat::R8G8_SNORM => Ok(Layout::Snorm { channels: 2 }),
+      DxgiFormat::R8_SNORM => Ok(Layout::Snorm { channels: 1 }),
+      format if format.uncompressed_masks().is_some() => Ok(Layout::Uncompressed),
+      // Planar formats can't be decoded as packed pixels, so call them out specifically
+      format if format.is_planar() => Err(DecodeError::UnsupportedLayout(format)),
+      _ => Err(DecodeError::UnsupportedDxgiFormat(format))
+    }
+  }
+}
 
 // Given a mask, we first take the bits we care about and shift them down to start at 0
-// After that, we convert them to being in the range [0, 256)
-fn uncompressed_convert_mask(pixel: u32, mask: u32) -> u8 {
-  ((pixel & mask) >> mask.trailing_zeros() * 255 / (2u32.pow(mask.count_ones()) - 1)) as u8
+// After that, we scale them to be in the range [0, max]. This works for channels of any
+// width, so the 3 and 2-bit channels of A8R3G3B2 still reach the full range.
+fn uncompressed_convert_mask(pixel: u32, mask: u32, max: u64) -> u64 {
+  if mask == 0 {
+    return 0;
+  };
+
+  let mask_max = (1u64 << mask.count_ones()) - 1;
+  ((pixel & mask) >> mask.trailing_zeros()) as u64 * max / mask_max
 }
 
-// Handles decoding an uncompressed buffer into a series of mipmap images
-pub fn decode_layers_uncompressed(header: &Header, mut buf: &[u8]) -> Vec<RgbaImage> {
+// Converts a little-endian pixel into RGBA format in the range [0, max] using the header's channel masks.
+// Pixels may be anywhere from 1 to 4 bytes wide, the missing high bytes of narrower pixels are zero.
+fn uncompressed_convert_pixel(p: &[u8], masks: [u32; 4], luminance: bool, max: u64) -> [u64; 4] {
+  let mut bytes = [0; 4];
+  let len = p.len().min(4);
+  bytes[..len].copy_from_slice(&p[..len]);
+  let pixel = u32::from_le_bytes(bytes);
+
+  // Formats without an alpha mask are treated as fully opaque
+  let alpha = match masks[3] {
+    0 => max,
+    mask => uncompressed_convert_mask(pixel, mask, max)
+  };
+
+  // Spread the gray value of luminance formats across all three color channels
+  let r = uncompressed_convert_mask(pixel, masks[0], max);
+  match luminance {
+    true => [r, r, r, alpha],
+    false => [
+      r,
+      uncompressed_convert_mask(pixel, masks[1], max),
+      uncompressed_convert_mask(pixel, masks[2], max),
+      alpha
+    ]
+  }
+}
+
+// Converts a 16-bit half precision float into a 32-bit float
+fn f16_to_f32(half: u16) -> f32 {
+  let sign = ((half >> 15) as u32) << 31;
+  let exponent = ((half >> 10) & 0x1F) as u32;
+  let mantissa = (half & 0x3FF) as u32;
+
+  let bits = match (exponent, mantissa) {
+    (0, 0) => sign,
+    // Subnormal halves become normal floats, so shift the mantissa up until it has a leading 1
+    (0, _) => {
+      let shift = mantissa.leading_zeros() - 21;
+      sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3FF) << 13)
+    },
+    // Infinity and NaN
+    (0x1F, _) => sign | 0x7F800000 | (mantissa << 13),
+    (_, _) => sign | ((exponent + 112) << 23) | (mantissa << 13)
+  };
+
+  f32::from_bits(bits)
+}
+
+// Clamps a float channel to [0, 1] and scales it to the range [0, 256)
+fn float_to_u8(value: f32) -> u8 {
+  (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Converts a pixel of 1-4 little-endian float channels into RGBA format. Missing color
+// channels are left black, and a missing alpha channel is treated as fully opaque
+fn float_convert_pixel(p: &[u8], half: bool) -> [f32; 4] {
+  let mut pixel = [0.0, 0.0, 0.0, 1.0];
+  let channel_bytes = if half { 2 } else { 4 };
+  for (channel, bytes) in pixel.iter_mut().zip(p.chunks(channel_bytes)) {
+    *channel = match half {
+      true => f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+      false => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+  };
+
+  pixel
+}
+
+// Converts a pixel of 1-4 signed normalized channels into RGBA format, remapping [-128, 127] onto
+// [0, 255]. Missing color channels are left black, and a missing alpha channel is treated as fully opaque
+fn snorm_convert_pixel(p: &[u8]) -> [u8; 4] {
+  let mut pixel = [0, 0, 0, 255];
+  for (channel, &byte) in pixel.iter_mut().zip(p) {
+    *channel = (byte as i8 as i16 + 128) as u8;
+  };
+
+  pixel
+}
+
+// Converts a pixel of 1-4 signed normalized channels into signed floats in the range [-1, 1].
+// Both -128 and -127 map to -1, as the format specifies.
+fn snorm_convert_pixel_signed(p: &[u8]) -> [f32; 4] {
+  let mut pixel = [0.0, 0.0, 0.0, 1.0];
+  for (channel, &byte) in pixel.iter_mut().zip(p) {
+    *channel = (byte as i8 as f32 / 127.0).max(-1.0);
+  };
+
+  pixel
+}
+
+// Converts a pixel of 1-4 little-endian 16-bit channels into RGBA format in the range [0, 65535],
+// remapping signed channels from [-32768, 32767]. Missing color channels are left black,
+// and a missing alpha channel is treated as fully opaque
+fn int16_convert_pixel(p: &[u8], signed: bool) -> [u16; 4] {
+  let mut pixel = [0, 0, 0, 65535];
+  for (channel, bytes) in pixel.iter_mut().zip(p.chunks(2)) {
+    let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+    *channel = match signed {
+      true => value ^ 0x8000,
+      false => value
+    };
+  };
+
+  pixel
+}
+
+// Converts a pixel of 1-4 little-endian signed 16-bit channels into signed floats in the range [-1, 1]
+fn int16_convert_pixel_signed(p: &[u8]) -> [f32; 4] {
+  let mut pixel = [0.0, 0.0, 0.0, 1.0];
+  for (channel, bytes) in pixel.iter_mut().zip(p.chunks(2)) {
+    *channel = (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0);
+  };
+
+  pixel
+}
+
+// Rows of the main image of each face may be padded out to the pitch given in
+// the header, mipmaps following it are always tightly packed
+fn layer_pitch(header: &Header, layer: usize, row_size: usize) -> usize {
+  let level = layer % header.mipmap_levels() as usize;
+  match header.pitch {
+    Some(pitch) if level == 0 && pitch > row_size => pitch,
+    _ => row_size
+  }
+}
+
+// Surfaces staged through D3D11 may pad each row of 4x4 blocks out to the pitch given in the header,
+// just like rows of pixels. Some writers put the linear size of the main image in the pitch instead,
+// which is told apart by exactly covering every row of blocks.
+fn block_row_pitch(header: &Header, layer: usize, row_size: usize, block_rows: usize) -> usize {
+  match layer_pitch(header, layer, row_size) {
+    pitch if block_rows > 1 && pitch == row_size * block_rows => row_size,
+    pitch => pitch
+  }
+}
+
+// Drops the padding after each row of blocks of a block compressed layer, see `block_row_pitch`
+pub fn unpad_block_rows<'a>(header: &Header, layer: usize, layer_data: &'a [u8], width: usize, height: usize, block_bytes: usize) -> Cow<'a, [u8]> {
+  let row_size = width.div_ceil(4) * block_bytes;
+  match block_row_pitch(header, layer, row_size, height.div_ceil(4)) {
+    pitch if pitch > row_size => layer_data.chunks(pitch)
+      .flat_map(|row| &row[..row_size.min(row.len())])
+      .copied()
+      .collect(),
+    _ => Cow::Borrowed(layer_data)
+  }
+}
+
+// Returns true if the layer falls within the mipmap levels the caller asked for
+fn wants_layer(header: &Header, layer: usize, options: &DecodeOptions) -> bool {
+  let level = layer % header.mipmap_levels() as usize;
+  options.max_level.is_none_or(|max_level| level <= max_level)
+}
+
+// The width, height and pixels of a decoded layer
+type PixelLayer<T> = (usize, usize, Vec<[T; 4]>);
+
+// Splits a buffer of fixed-size pixels into its mipmap layers, using `convert` to turn
+// the bytes of each pixel into an RGBA pixel. Returns the width, height and pixels of each layer.
+fn decode_pixels<T, F>(header: &Header, mut buf: &[u8], pixel_bytes: usize, options: &DecodeOptions, convert: F) -> Result<Vec<PixelLayer<T>>, DecodeError>
+where F: Fn(&[u8]) -> [T; 4] {
   let layer_sizes = header.get_layer_sizes();
   let mut layers = Vec::with_capacity(layer_sizes.len());
-  for (h, w) in layer_sizes {
-    let layer_size = h * w * header.pixel_bytes;
-    let (layer_data, new_buf) = buf.split_at(layer_size);
+  for (i, (h, w)) in layer_sizes.into_iter().enumerate() {
+    let row_size = w * pixel_bytes;
+    let pitch = layer_pitch(header, i, row_size);
+
+    // Volumes store every slice of a level in turn, and only the front one is decoded
+    let layer_size = h * pitch;
+    let (layer_data, new_buf) = buf.split_at((layer_size * header.layer_depth(i)).min(buf.len()));
     buf = new_buf;
 
-    // Chunk into groups of 3 or 4, then convert to normalized RGBA format
-    let mut layer = Vec::with_capacity(layer_data.len() / header.pixel_bytes);
-    for p in layer_data.chunks(header.pixel_bytes) {
-      let pixel: u32 = p[0] as u32 + ((p[1] as u32) << 8) + ((p[2] as u32) << 16) + ((p[3] as u32) << 24);
+    if !wants_layer(header, i, options) {
+      continue;
+    };
 
-      layer.push([
-        uncompressed_convert_mask(pixel, header.channel_masks[0]),
-        uncompressed_convert_mask(pixel, header.channel_masks[1]),
-        uncompressed_convert_mask(pixel, header.channel_masks[2]),
-        uncompressed_convert_mask(pixel, header.channel_masks[3])
-      ]);
+    let layer_data = layer_data.get(..layer_size)
+      .ok_or(DecodeError::InvalidLayerLength { expected: layer_size, found: layer_data.len() })?;
+
+    // Chunk into groups of `pixel_bytes`, then convert to RGBA format
+    let layer = layer_data.chunks(pitch)
+      .flat_map(|row| row[..row_size].chunks(pixel_bytes))
+      .map(&convert)
+      .collect::<Vec<_>>();
+
+    layers.push((w, h, layer));
+  };
+
+  Ok(layers)
+}
+
+// Scales an 8-bit channel down to the width of `mask` and shifts it into place, the inverse of `uncompressed_convert_mask`
+fn uncompressed_pack_mask(value: u8, mask: u32) -> u32 {
+  if mask == 0 {
+    return 0;
+  };
+
+  let mask_max = (1u64 << mask.count_ones()) - 1;
+  (((value as u64 * mask_max + 127) / 255) << mask.trailing_zeros()) as u32
+}
+
+// Thresholds for ordered dithering, spread so that neighbouring pixels round in different directions
+const BAYER_4X4: [[u8; 4]; 4] = [
+  [0, 8, 2, 10],
+  [12, 4, 14, 6],
+  [3, 11, 1, 9],
+  [15, 7, 13, 5]
+];
+
+// Nudges an 8-bit channel by up to half a quantization step of `mask`, according to `threshold`.
+// Channels 8 bits wide or more have nothing to quantize, so they are left alone.
+fn dither_channel(value: u8, mask: u32, threshold: u8) -> u8 {
+  let bits = mask.count_ones();
+  if bits == 0 || bits >= 8 {
+    return value;
+  };
+
+  let step = 255.0 / ((1u32 << bits) - 1) as f32;
+  let offset = ((threshold as f32 + 0.5) / 16.0 - 0.5) * step;
+  (value as f32 + offset).round().clamp(0.0, 255.0) as u8
+}
+
+// Packs RGBA layers into tightly packed little-endian pixels, using the header's channel masks.
+// With `dither`, channels are ordered dithered before being rounded down to the width of their masks.
+pub fn encode_layers_uncompressed(header: &Header, layers: &[RgbaImage], dither: bool) -> Vec<u8> {
+  let mut buf = Vec::with_capacity(layers.iter().map(|layer| layer.pixels().len() * header.pixel_bytes).sum());
+  for (x, y, pixel) in layers.iter().flat_map(|layer| layer.enumerate_pixels()) {
+    let threshold = BAYER_4X4[y as usize % 4][x as usize % 4];
+    let packed = pixel.0.iter().zip(header.channel_masks.iter())
+      .map(|(&channel, &mask)| match dither {
+        true => (dither_channel(channel, mask, threshold), mask),
+        false => (channel, mask)
+      })
+      .fold(0, |memo, (channel, mask)| memo | uncompressed_pack_mask(channel, mask));
+    buf.extend_from_slice(&packed.to_le_bytes()[..header.pixel_bytes]);
+  };
+
+  buf
+}
+
+/// Lazily decodes the pixels of the main image of an uncompressed file, given its header and
+/// the payload that follows it. Pixels are yielded in RGBA format row by row, without
+/// allocating an image, and the iterator ends early if the payload is too short.
+///
+/// Fails for compressed files, and for uncompressed files that aren't described by channel masks.
+pub fn iter_uncompressed_pixels<'a>(header: &'a Header, buf: &'a [u8]) -> Result<impl Iterator<Item = [u8; 4]> + 'a, DecodeError> {
+  if Layout::from_header(header)? != Layout::Uncompressed {
+    return Err(DecodeError::UnsupportedCompression(header.compression));
+  };
+
+  let luminance = header.pixel_format.is_luminance();
+  let pixel_bytes = header.pixel_bytes;
+  let row_size = header.width as usize * pixel_bytes;
+  let pitch = layer_pitch(header, 0, row_size);
+  let layer_data = &buf[..(header.height as usize * pitch).min(buf.len())];
+
+  Ok(layer_data.chunks(pitch)
+    .flat_map(move |row| row[..row_size.min(row.len())].chunks_exact(pixel_bytes))
+    .map(move |p| uncompressed_convert_pixel(p, header.channel_masks, luminance, 255).map(|c| c as u8)))
+}
+
+// Handles decoding an uncompressed buffer into a series of mipmap images
+pub fn decode_layers_uncompressed(header: &Header, buf: &[u8], options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  // Dropping the alpha mask makes the pixels opaque, like formats without an alpha channel
+  let mut masks = header.channel_masks;
+  if options.ignore_alpha || (options.opaque_2bit_alpha && masks[3].count_ones() == 2) {
+    masks[3] = 0;
+  };
+
+  // The X bits are whichever bits of the pixel no channel claims
+  let luminance = header.pixel_format.is_luminance();
+  if options.preserve_x_bits && !options.ignore_alpha && masks[3] == 0 && !luminance {
+    let pixel_mask = u32::MAX.checked_shr(32 - header.bits_per_pixel.min(32)).unwrap_or(0);
+    masks[3] = pixel_mask & !(masks[0] | masks[1] | masks[2]);
+  };
+
+  let convert = |p: &[u8]| {
+    uncompressed_convert_pixel(p, masks, luminance, 255).map(|c| c as u8)
+  };
+
+  Ok(decode_pixels(header, buf, header.pixel_bytes, options, convert)?.into_iter()
+    .map(|(w, h, layer)| finish_layer(layer, w, h, options))
+    .collect())
+}
+
+// Handles decoding an uncompressed floating point buffer into a series of mipmap images
+pub fn decode_layers_float(header: &Header, buf: &[u8], channels: usize, half: bool, options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  let pixel_bytes = channels * if half { 2 } else { 4 };
+  let convert = |p: &[u8]| float_convert_pixel(p, half).map(float_to_u8);
+
+  Ok(decode_pixels(header, buf, pixel_bytes, options, convert)?.into_iter()
+    .map(|(w, h, layer)| finish_layer(layer, w, h, options))
+    .collect())
+}
+
+// Handles decoding an uncompressed signed normalized buffer into a series of mipmap images
+pub fn decode_layers_snorm(header: &Header, buf: &[u8], channels: usize, options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  Ok(decode_pixels(header, buf, channels, options, snorm_convert_pixel)?.into_iter()
+    .map(|(w, h, layer)| finish_layer(layer, w, h, options))
+    .collect())
+}
+
+// Handles decoding an uncompressed 16-bit integer buffer into a series of mipmap images
+pub fn decode_layers_int16(header: &Header, buf: &[u8], channels: usize, signed: bool, options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  let convert = |p: &[u8]| int16_convert_pixel(p, signed).map(|c| (c as u32 * 255 / 65535) as u8);
+
+  Ok(decode_pixels(header, buf, channels * 2, options, convert)?.into_iter()
+    .map(|(w, h, layer)| finish_layer(layer, w, h, options))
+    .collect())
+}
+
+// Converts a studio swing BT.601 YUV sample into an opaque RGBA pixel
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> [u8; 4] {
+  let c = 298 * (y as i32 - 16) + 128;
+  let d = u as i32 - 128;
+  let e = v as i32 - 128;
+  let clamp = |value: i32| (value >> 8).clamp(0, 255) as u8;
+  [clamp(c + 409 * e), clamp(c - 100 * d - 208 * e), clamp(c + 516 * d), 255]
+}
+
+// Handles decoding a packed YUV 4:2:2 buffer into a series of mipmap images
+pub fn decode_layers_yuv422(header: &Header, mut buf: &[u8], uyvy: bool, options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  let layer_sizes = header.get_layer_sizes();
+  let mut layers = Vec::with_capacity(layer_sizes.len());
+  for (i, (h, w)) in layer_sizes.into_iter().enumerate() {
+    let row_size = w.div_ceil(2) * 4;
+    let pitch = layer_pitch(header, i, row_size);
+
+    // Volumes store every slice of a level in turn, and only the front one is decoded
+    let layer_size = h * pitch;
+    let (layer_data, new_buf) = buf.split_at((layer_size * header.layer_depth(i)).min(buf.len()));
+    buf = new_buf;
+
+    if !wants_layer(header, i, options) {
+      continue;
     };
 
-    let layer = pixels_into_bytes(layer);
-    let layer = RgbaImage::from_raw(w as u32, h as u32, layer)
-      .expect("error converting bytes to image buffer");
-    layers.push(layer);
+    let layer_data = layer_data.get(..layer_size)
+      .ok_or(DecodeError::InvalidLayerLength { expected: layer_size, found: layer_data.len() })?;
+
+    // Expand each pair into two pixels, dropping the padding pixel of rows with an odd width
+    let layer = layer_data.chunks(pitch)
+      .flat_map(|row| {
+        row[..row_size].chunks(4)
+          .flat_map(|pair| {
+            let [y0, u, y1, v] = match uyvy {
+              true => [pair[1], pair[0], pair[3], pair[2]],
+              false => [pair[0], pair[1], pair[2], pair[3]]
+            };
+            [yuv_to_rgba(y0, u, v), yuv_to_rgba(y1, u, v)]
+          })
+          .take(w)
+      })
+      .collect::<Vec<_>>();
+
+    layers.push(finish_layer(layer, w, h, options));
   };
 
-  layers
+  Ok(layers)
+}
+
+// Flattens decoded layers into image buffers of any channel type
+fn layers_into_images<T: Primitive + 'static>(layers: Vec<PixelLayer<T>>) -> Vec<ImageBuffer<Rgba<T>, Vec<T>>> {
+  layers.into_iter()
+    .map(|(w, h, layer)| {
+      let layer = layer.into_iter().flatten().collect::<Vec<T>>();
+      ImageBuffer::from_raw(w as u32, h as u32, layer)
+        .expect("error converting channels to image buffer")
+    })
+    .collect()
+}
+
+// Handles decoding a buffer into a series of mipmap images, keeping the precision of the source format
+pub fn decode_layers_native(header: &Header, buf: &[u8], options: &DecodeOptions) -> Result<NativeDds, DecodeError> {
+  let layout = Layout::from_header(header)?;
+  check_tiling(layout, options)?;
+
+  match layout {
+    Layout::Float { channels, half } => {
+      let pixel_bytes = channels * if half { 2 } else { 4 };
+      let layers = decode_pixels(header, buf, pixel_bytes, options, |p| float_convert_pixel(p, half))?;
+      Ok(NativeDds::F32(layers_into_images(layers)))
+    },
+    // Signed channels keep their sign, so they can be used as vectors directly
+    Layout::Snorm { channels } => {
+      let layers = decode_pixels(header, buf, channels, options, snorm_convert_pixel_signed)?;
+      Ok(NativeDds::F32(layers_into_images(layers)))
+    },
+    Layout::Int16 { channels, signed: true } => {
+      let layers = decode_pixels(header, buf, channels * 2, options, int16_convert_pixel_signed)?;
+      Ok(NativeDds::F32(layers_into_images(layers)))
+    },
+    Layout::Int16 { channels, signed: false } => {
+      let layers = decode_pixels(header, buf, channels * 2, options, |p| int16_convert_pixel(p, false))?;
+      Ok(NativeDds::U16(layers_into_images(layers)))
+    },
+    // Any channel wider than 8 bits would lose precision as a `u8`
+    Layout::Uncompressed if header.channel_masks.iter().any(|mask| mask.count_ones() > 8) => {
+      let luminance = header.pixel_format.is_luminance();
+      let convert = |p: &[u8]| {
+        uncompressed_convert_pixel(p, header.channel_masks, luminance, 65535).map(|c| c as u16)
+      };
+
+      let layers = decode_pixels(header, buf, header.pixel_bytes, options, convert)?;
+      Ok(NativeDds::U16(layers_into_images(layers)))
+    },
+    _ => Ok(NativeDds::U8(decode_layers(header, buf, options)?))
+  }
+}
+
+// Converts each pixel of decoded layers into another pixel type
+fn convert_layers<T, P>(layers: Vec<ImageBuffer<Rgba<T>, Vec<T>>>, convert: fn([T; 4]) -> P) -> Vec<DecodedImage<P>>
+where T: Primitive + 'static, P: DecodedPixel {
+  layers.iter()
+    .map(|layer| ImageBuffer::from_fn(layer.width(), layer.height(), |x, y| convert(layer.get_pixel(x, y).0)))
+    .collect()
+}
+
+// Decodes each layer at its native precision, then converts it into the requested pixel type
+pub fn decode_layers_as<P: DecodedPixel>(header: &Header, buf: &[u8], options: &DecodeOptions) -> Result<Vec<DecodedImage<P>>, DecodeError> {
+  match decode_layers_native(header, buf, options)? {
+    NativeDds::U8(layers) => Ok(convert_layers(layers, P::from_u8)),
+    NativeDds::U16(layers) => Ok(convert_layers(layers, P::from_u16)),
+    NativeDds::F32(layers) => Ok(convert_layers(layers, P::from_f32))
+  }
+}
+
+// Endpoint colors inflated from 5 and 6-bit to 8-bit, so the divisions
+// are done once up front rather than for every pixel
+const INFLATE_5: [u32; 32] = inflate_table();
+const INFLATE_6: [u32; 64] = inflate_table();
+
+const fn inflate_table<const N: usize>() -> [u32; N] {
+  let mut table = [0; N];
+  let mut i = 0;
+  while i < N {
+    table[i] = i as u32 * 255 / (N as u32 - 1);
+    i += 1;
+  };
+
+  table
 }
 
 // Implements this lookup table for calculating pixel colors
@@ -49,10 +594,10 @@ pub fn decode_layers_uncompressed(header: &Header, mut buf: &[u8]) -> Vec<RgbaIm
 //   3  | (c0 + 2*c1) / 3 |      black
 //
 // Returns an Option to differentiate between a black pixel and a transparent pixel
-fn dxt1_lookup(key: (bool, u8), c0: u32, c1: u32, inflate_by: u32) -> Option<u32> {
+fn dxt1_lookup(key: (bool, u8), c0: u32, c1: u32, inflate: &[u32]) -> Option<u32> {
   // Inflate colors from 5/6-bit to 8-bit
-  let c0 = c0 * 255 / (2u32.pow(inflate_by) - 1);
-  let c1 = c1 * 255 / (2u32.pow(inflate_by) - 1);
+  let c0 = inflate[c0 as usize];
+  let c1 = inflate[c1 as usize];
 
   match key {
     (true, 0) => Some(c0),
@@ -67,15 +612,16 @@ fn dxt1_lookup(key: (bool, u8), c0: u32, c1: u32, inflate_by: u32) -> Option<u32
   }
 }
 
-// Handles decoding a DXT1-compressed 64-bit buffer into 16 pixels. Handles 1-bit alpha variant with `alpha` parameter
-fn decode_chunk_dxt1(bytes: &[u8], alpha: bool) -> Vec<[u8; 4]> {
+// Handles decoding a DXT1-compressed 64-bit buffer into 16 pixels, appended to `layer`. Handles 1-bit alpha variant with `alpha` parameter
+fn decode_chunk_dxt1(bytes: &[u8], alpha: bool, layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  check_block_length(bytes, 8)?;
+
   // Convert to `u32` to allow overflow for arithmetic below
   let color0 = (((bytes[1] as u16) << 8) + bytes[0] as u16) as u32;
   let color1 = (((bytes[3] as u16) << 8) + bytes[2] as u16) as u32;
 
   // Iterate through each pair of bits in each `code` byte to
   // determine the color for each pixel
-  let mut layer = Vec::with_capacity((bytes.len() - 4) * 4);
   for &code in bytes[4..].iter().rev() {
     for i in 0..4 {
       let red0 = (color0 & 0xF800) >> 11;
@@ -85,12 +631,13 @@ fn decode_chunk_dxt1(bytes: &[u8], alpha: bool) -> Vec<[u8; 4]> {
       let blue0 = color0 & 0x1F;
       let blue1 = color1 & 0x1F;
 
-      // If alpha is disabled or if any channel is non-black, show the pixel, otherwise, hide it
+      // Only index 3 in the three color mode is transparent, and only when alpha is enabled.
+      // Every other index is opaque, even if the color it refers to happens to be black.
       let key = (color0 > color1, (code >> (i * 2)) & 0x3);
-      let r = dxt1_lookup(key, red0, red1, 5);
-      let g = dxt1_lookup(key, green0, green1, 6);
-      let b = dxt1_lookup(key, blue0, blue1, 5);
-      let a = if !alpha || r.is_some() || g.is_some() || b.is_some() { 255 } else { 0 };
+      let r = dxt1_lookup(key, red0, red1, &INFLATE_5);
+      let g = dxt1_lookup(key, green0, green1, &INFLATE_6);
+      let b = dxt1_lookup(key, blue0, blue1, &INFLATE_5);
+      let a = if alpha && key == (false, 3) { 0 } else { 255 };
 
       layer.push([
         r.unwrap_or(0) as u8,
@@ -101,7 +648,16 @@ fn decode_chunk_dxt1(bytes: &[u8], alpha: bool) -> Vec<[u8; 4]> {
     };
   };
 
-  layer
+  Ok(())
+}
+
+// Returns true if any DXT1 block in the buffer uses the transparent index of its three color mode
+pub fn uses_punch_through_alpha(buf: &[u8]) -> bool {
+  buf.chunks_exact(8).any(|block| {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    color0 <= color1 && block[4..].iter().any(|&code| (0..4).any(|i| (code >> (i * 2)) & 0x3 == 0x3))
+  })
 }
 
 fn dxt3_lookup(key: u8, c0: u32, c1: u32, inflate_by: u32) -> u32 {
@@ -118,14 +674,15 @@ fn dxt3_lookup(key: u8, c0: u32, c1: u32, inflate_by: u32) -> u32 {
   }
 }
 
-// Handles decoding a DXT2/3-compressed 128-bit buffer into 16 pixels
-fn decode_chunk_dxt3(bytes: &[u8]) -> Vec<[u8; 4]> {
+// Handles decoding a DXT2/3-compressed 128-bit buffer into 16 pixels, appended to `layer`
+fn decode_chunk_dxt3(bytes: &[u8], layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  check_block_length(bytes, 16)?;
+
   // Convert to `u32` to allow overflow for arithmetic below
   let color0 = (((bytes[9] as u16) << 8) + bytes[8] as u16) as u32;
   let color1 = (((bytes[11] as u16) << 8) + bytes[10] as u16) as u32;
 
   // Iterate through each pair of bits in each `code` byte to determine the color for each pixel
-  let mut layer = Vec::with_capacity((bytes.len() - 12) * 4);
   for (i, &code) in bytes[12..].iter().rev().enumerate() {
     for j in 0..4 {
       let alpha_nibble = (bytes[2 * (3 - i) + j / 2] >> (4 * (j % 2))) & 0xF;
@@ -147,7 +704,7 @@ fn decode_chunk_dxt3(bytes: &[u8]) -> Vec<[u8; 4]> {
     };
   };
 
-  layer
+  Ok(())
 }
 
 // Implements this lookup table for calculating pixel colors
@@ -177,26 +734,29 @@ fn dxt5_alpha_interp(alpha0: u32, alpha1: u32, key: u64) -> u32 {
   match (alpha0 > alpha1, key) {
     (true, 0) => alpha0,
     (true, 1) => alpha1,
-    (true, 2) => (6 * alpha0 + 1 * alpha1) / 7,
+    (true, 2) => (6 * alpha0 + alpha1) / 7,
     (true, 3) => (5 * alpha0 + 2 * alpha1) / 7,
     (true, 4) => (4 * alpha0 + 3 * alpha1) / 7,
     (true, 5) => (3 * alpha0 + 4 * alpha1) / 7,
     (true, 6) => (2 * alpha0 + 5 * alpha1) / 7,
-    (true, 7) => (1 * alpha0 + 6 * alpha1) / 7,
+    (true, 7) => (alpha0 + 6 * alpha1) / 7,
     (false, 0) => alpha0,
     (false, 1) => alpha1,
-    (false, 2) => (4 * alpha0 + 1 * alpha1) / 5,
+    (false, 2) => (4 * alpha0 + alpha1) / 5,
     (false, 3) => (3 * alpha0 + 2 * alpha1) / 5,
     (false, 4) => (2 * alpha0 + 3 * alpha1) / 5,
-    (false, 5) => (1 * alpha0 + 4 * alpha1) / 5,
+    (false, 5) => (alpha0 + 4 * alpha1) / 5,
     (false, 6) => 0,
     (false, 7) => 255,
     t => unreachable!("Unexpected value: {:?}", t)
   }
 }
 
-// Handles decoding a DXT4/5-compressed 128-bit buffer into 16 pixels
-fn decode_chunk_dxt5(bytes: &[u8]) -> Vec<[u8; 4]> {
+// Handles decoding a DXT4/5-compressed 128-bit buffer into 16 pixels, appended to `layer`.
+// Without `alpha`, the alpha block is skipped entirely and every pixel is opaque.
+fn decode_chunk_dxt5(bytes: &[u8], alpha: bool, layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  check_block_length(bytes, 16)?;
+
   let color0 = (((bytes[9] as u16) << 8) + bytes[8] as u16) as u32;
   let color1 = (((bytes[11] as u16) << 8) + bytes[10] as u16) as u32;
 
@@ -205,8 +765,7 @@ fn decode_chunk_dxt5(bytes: &[u8]) -> Vec<[u8; 4]> {
 
   // Convert 6 u8's into a single 48 bit number, to make it easier to grab 3-bit chunks out of them
   let alpha_info = bytes[2..8].iter().enumerate()
-    .fold(0u64, |memo, (i, &x)| memo + ((x as u64) << 8 * i) as u64);
-  let mut layer = Vec::with_capacity((bytes.len() - 12) * 4);
+    .fold(0u64, |memo, (i, &x)| memo + ((x as u64) << (8 * i)));
   for (i, &code) in bytes[12..].iter().rev().enumerate() {
     for j in 0..4 {
       let red0 = (color0 & 0xF800) >> 11;
@@ -216,8 +775,10 @@ fn decode_chunk_dxt5(bytes: &[u8]) -> Vec<[u8; 4]> {
       let blue0 = color0 & 0x1F;
       let blue1 = color1 & 0x1F;
 
-      let alpha_key = (alpha_info >> (3 * (4 * (3 - i) + j))) & 0x07;
-      let alpha = dxt5_alpha_interp(alpha0, alpha1, alpha_key);
+      let alpha = match alpha {
+        true => dxt5_alpha_interp(alpha0, alpha1, (alpha_info >> (3 * (4 * (3 - i) + j))) & 0x07),
+        false => 255
+      };
 
       let key = (code >> (j * 2)) & 0x3;
       layer.push([
@@ -229,21 +790,134 @@ fn decode_chunk_dxt5(bytes: &[u8]) -> Vec<[u8; 4]> {
     };
   };
 
-  layer
+  Ok(())
+}
+
+// Decodes an 8 byte block encoded like the alpha of a DXT5 block into its 16 values, in scanline order
+fn decode_alpha_block(bytes: &[u8]) -> [u8; 16] {
+  let alpha0 = bytes[0] as u32;
+  let alpha1 = bytes[1] as u32;
+
+  // Each value has a 3-bit index, packed from the least significant bit up
+  let mut indices = BitReader::new(&bytes[2..8]);
+  let mut values = [0; 16];
+  for value in values.iter_mut() {
+    *value = dxt5_alpha_interp(alpha0, alpha1, indices.read(3)) as u8;
+  };
+
+  values
+}
+
+// Handles decoding a BC4-compressed 64-bit buffer into 16 pixels, appended to `layer`.
+// The single channel is decoded into red, like the other single channel formats
+fn decode_chunk_bc4(bytes: &[u8], layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  check_block_length(bytes, 8)?;
+
+  // Pixels are pushed in the same order as the other block decoders, with the rows reversed
+  let red = decode_alpha_block(bytes);
+  for i in 0..4 {
+    for j in 0..4 {
+      layer.push([red[4 * (3 - i) + j], 0, 0, 255]);
+    };
+  };
+
+  Ok(())
+}
+
+// Handles decoding a BC5-compressed 128-bit buffer into 16 pixels, appended to `layer`.
+// The two channels are decoded into red and green, leaving blue empty
+fn decode_chunk_bc5(bytes: &[u8], layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  check_block_length(bytes, 16)?;
+
+  let red = decode_alpha_block(&bytes[0..8]);
+  let green = decode_alpha_block(&bytes[8..16]);
+  for i in 0..4 {
+    for j in 0..4 {
+      let texel = 4 * (3 - i) + j;
+      layer.push([red[texel], green[texel], 0, 255]);
+    };
+  };
+
+  Ok(())
+}
+
+// Rebuilds the blue channel of a tangent space normal map that only stores X and Y in red and green,
+// since Z is always positive and the normal has unit length. Vectors too long to be normals are
+// scaled back down to unit length, leaving Z at 0.
+pub fn reconstruct_z(pixel: &mut [u8; 4]) {
+  let x = pixel[0] as f32 / 127.5 - 1.0;
+  let y = pixel[1] as f32 / 127.5 - 1.0;
+  let length_squared = x * x + y * y;
+  let (x, y, z) = match length_squared > 1.0 {
+    true => (x / length_squared.sqrt(), y / length_squared.sqrt(), 0.0),
+    false => (x, y, (1.0 - length_squared).sqrt())
+  };
+
+  let to_u8 = |value: f32| ((value + 1.0) * 127.5).round().clamp(0.0, 255.0) as u8;
+  *pixel = [to_u8(x), to_u8(y), to_u8(z), 255];
+}
+
+// Decodes a DXT5 block as usual, then moves X out of alpha and rebuilds Z from X and Y
+fn decode_chunk_dxt5nm(bytes: &[u8], layer: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  decode_chunk_dxt5(bytes, true, layer)?;
+  let start = layer.len() - 16;
+  for pixel in &mut layer[start..] {
+    *pixel = [pixel[3], pixel[1], 0, 255];
+    reconstruct_z(pixel);
+  };
+
+  Ok(())
+}
+
+// Decodes only the alpha blocks of a single DXT5 layer into a grayscale image, which
+// is much faster than a full decode since the color blocks are skipped entirely
+pub fn decode_layer_dxt5_alpha(buf: &[u8], width: u32, height: u32) -> Result<GrayImage, DecodeError> {
+  let blocks_wide = width.div_ceil(4);
+  let blocks = blocks_wide * height.div_ceil(4);
+  let mut image = GrayImage::new(width, height);
+  for block in 0..blocks {
+    let start = (block as usize * 16).min(buf.len());
+    let end = (start + 16).min(buf.len());
+    check_block_length(&buf[start..end], 16)?;
+
+    let (x, y) = (block % blocks_wide * 4, block / blocks_wide * 4);
+    for (i, &alpha) in decode_alpha_block(&buf[start..start + 8]).iter().enumerate() {
+      let (x, y) = (x + i as u32 % 4, y + i as u32 / 4);
+      if x < width && y < height {
+        image.put_pixel(x, y, Luma([alpha]));
+      };
+    };
+  };
+
+  Ok(image)
 }
 
-fn dxt_chunk_transform(chunk: &[u8], header: &Header) -> Vec<[u8; 4]> {
-  match header.compression {
-    Compression::DXT1 => decode_chunk_dxt1(chunk, header.pixel_format != PixelFormat::Unknown),
-    Compression::DXT2 | Compression::DXT3 => decode_chunk_dxt3(chunk),
-    Compression::DXT4 | Compression::DXT5 => decode_chunk_dxt5(chunk),
-    _ => unreachable!(format!("This function cannot handle `{:?}` images", header.compression))
+// The lookups in the block decoders assume a complete block, and would index out of bounds otherwise
+fn check_block_length(bytes: &[u8], expected: usize) -> Result<(), DecodeError> {
+  match bytes.len() {
+    found if found == expected => Ok(()),
+    found => Err(DecodeError::InvalidBlockLength { expected, found })
   }
 }
 
-fn dxt_transpose_texels(chunk: &[[u8; 4]], w: usize, width: usize) -> Vec<[u8; 4]> {
-  let mut pixels = Vec::new();
-  for i in (0..4).rev() {
+fn dxt_chunk_transform(chunk: &[u8], layout: Layout, ignore_alpha: bool, pixels: &mut Vec<[u8; 4]>) -> Result<(), DecodeError> {
+  match layout {
+    Layout::Dxt1 { alpha } => decode_chunk_dxt1(chunk, alpha, pixels),
+    Layout::Dxt3 => decode_chunk_dxt3(chunk, pixels),
+    Layout::Dxt5 => decode_chunk_dxt5(chunk, !ignore_alpha, pixels),
+    Layout::Dxt5nm => decode_chunk_dxt5nm(chunk, pixels),
+    Layout::Bc4 => decode_chunk_bc4(chunk, pixels),
+    Layout::Bc5 => decode_chunk_bc5(chunk, pixels),
+    _ => unreachable!("This function cannot handle `{:?}` images", layout)
+  }
+}
+
+// Appends the texels of a row of blocks to `pixels` in scanline order, skipping
+// the padding rows of the last row of blocks. The block decoders emit each block's rows
+// bottom to top, so the top `rows` rows are the last ones in each block. Textures smaller
+// than a block only take the top-left `width` by `rows` window out of their only block.
+fn dxt_transpose_texels(chunk: &[[u8; 4]], w: usize, width: usize, rows: usize, pixels: &mut Vec<[u8; 4]>) {
+  for i in (4 - rows..4).rev() {
     for j in 0..(w / 4) {
       // If this is the last block in a row and the image width is not evenly divisible by 4, we
       // only push enough pixels to fill the rest of the block width
@@ -254,15 +928,81 @@ fn dxt_transpose_texels(chunk: &[[u8; 4]], w: usize, width: usize) -> Vec<[u8; 4
       }
     }
   }
+}
+
+// Blocks with every bit cleared or set are rare in real textures, but typical of data that
+// was zeroed out or never written. Fails if more than `threshold` percent of the blocks look like this.
+fn verify_blocks(layer_data: &[u8], chunk_size: usize, threshold: u8) -> Result<(), DecodeError> {
+  let total = layer_data.len() / chunk_size;
+  let suspicious = layer_data.chunks_exact(chunk_size)
+    .filter(|block| block.iter().all(|&b| b == 0x00) || block.iter().all(|&b| b == 0xFF))
+    .count();
+
+  match suspicious * 100 > total * threshold as usize {
+    true => Err(DecodeError::LikelyCorrupt { suspicious, total }),
+    false => Ok(())
+  }
+}
+
+// Returns the position of the block at (`x`, `y`) along a Z-order curve, which interleaves the
+// low `bits` bits of each coordinate. Grids that aren't square are stored as a row or column of
+// square grids, one after the other, so the remaining high bits pick which one.
+fn morton_index(x: usize, y: usize, bits: u32) -> usize {
+  let mut index = 0;
+  for bit in 0..bits {
+    index |= ((x >> bit) & 1) << (2 * bit) | ((y >> bit) & 1) << (2 * bit + 1);
+  };
+
+  index | ((x >> bits) | (y >> bits)) << (2 * bits)
+}
+
+// Reorders the blocks of a tiled layer into scanline order. Blocks missing from a truncated layer are left zeroed.
+fn detile_blocks(layer_data: &[u8], blocks_wide: usize, blocks_high: usize, block_bytes: usize, mode: TileMode) -> Result<Vec<u8>, DecodeError> {
+  if !blocks_wide.is_power_of_two() || !blocks_high.is_power_of_two() {
+    return Err(DecodeError::UnsupportedTiling(mode));
+  };
+
+  let bits = blocks_wide.min(blocks_high).trailing_zeros();
+  let mut linear = vec![0; layer_data.len()];
+  for y in 0..blocks_high {
+    for x in 0..blocks_wide {
+      let src = match mode {
+        TileMode::Morton => morton_index(x, y, bits) * block_bytes
+      };
+      let dst = (y * blocks_wide + x) * block_bytes;
+      if let (Some(block), Some(out)) = (layer_data.get(src..src + block_bytes), linear.get_mut(dst..dst + block_bytes)) {
+        out.copy_from_slice(block);
+      };
+    };
+  };
+
+  Ok(linear)
+}
 
-  pixels
+// Mislabeled files with uncompressed pixels under a block compressed four-cc would otherwise decode as noise.
+// Those payloads are told apart by being at least four times too large, exactly matching 16, 24 or 32-bit
+// pixels. Smaller excesses are left alone, since they're more likely mipmaps the header doesn't mention.
+fn check_payload_format(header: &Header, buf: &[u8], layer_sizes: &[(usize, usize)], layer_byte_sizes: &[usize]) -> Result<(), DecodeError> {
+  let expected = layer_byte_sizes.iter().sum::<usize>();
+  let pixels = layer_sizes.iter().enumerate().map(|(i, (h, w))| h * w * header.layer_depth(i)).sum::<usize>();
+  match buf.len() {
+    found if found >= expected * 4 && (2..=4).any(|pixel_bytes| found == pixels * pixel_bytes) => {
+      Err(DecodeError::PayloadFormatMismatch { compression: header.compression, expected, found })
+    },
+    _ => Ok(())
+  }
 }
 
 // Handles decoding a DXT1-5 compressed buffer into a series of mipmap images
-pub fn decode_layers_dxt(header: &Header, mut buf: &[u8]) -> Vec<RgbaImage> {
+pub fn decode_layers_dxt(header: &Header, mut buf: &[u8], layout: Layout, options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
   let layer_sizes = header.get_layer_sizes();
+  let layer_byte_sizes = layout.layer_byte_sizes(header);
+  check_payload_format(header, buf, &layer_sizes, &layer_byte_sizes)?;
   let mut layers = Vec::with_capacity(layer_sizes.len());
-  for (height, width) in layer_sizes {
+  // Decoded blocks are staged here before being transposed into each layer. The
+  // first layer is the largest, so the buffer never needs to grow after that.
+  let mut scratch: Vec<[u8; 4]> = Vec::new();
+  for (i, ((height, width), layer_size)) in layer_sizes.into_iter().zip(layer_byte_sizes).enumerate() {
     // We calculate the actual height and width here. Although the given height/width
     // can go down to 1, the block sizes are minimum 4x4, which we enforce here. We
     // then also round up to the nearest even divisor of 4. For example, a 47x49 texture
@@ -270,55 +1010,178 @@ pub fn decode_layers_dxt(header: &Header, mut buf: &[u8]) -> Vec<RgbaImage> {
     let h = (height.max(4) as f32 / 4.0).ceil() as usize * 4;
     let w = (width.max(4) as f32 / 4.0).ceil() as usize * 4;
 
-    // DXT1 compression uses 64 bits per 16 pixels, while DXT2-5 use 128 bits.
-    // Calculate how many total bytes to read out of the buffer for each layer
-    // here, as well as how big each individual chunk size is.
-    let (layer_size, chunk_size) = match header.compression {
-      Compression::DXT1 => (h * w / 2, 8),
-      _ => (h * w, 16)
-    };
+    let chunk_size = layout.block_bytes().expect("layout is not block compressed");
 
-    let (layer_data, new_buf) = buf.split_at(layer_size);
+    // A truncated payload leaves the final blocks short or missing, which is reported below
+    let (layer_data, new_buf) = buf.split_at(layer_size.min(buf.len()));
     buf = new_buf;
 
-    let layer = layer_data
-      // Chunk into blocks of appropriate size
-      .chunks(chunk_size)
-      // Turn those blocks into 16 RGBA pixels, and flatten into a
-      // vec of pixels for the entire image. Follow here for the dirty details:
-      // https://www.khronos.org/opengl/wiki/S3_Texture_Compression
-      .flat_map(|chunk| dxt_chunk_transform(chunk, header))
-      .collect::<Vec<_>>()
-      // Since the 16 byte pixel blocks are actually 4x4 texels, group image
-      // into chunks of four rows each, and then transpose into a row of texels.
-      .chunks(4 * w)
-      .flat_map(|chunk| dxt_transpose_texels(chunk, w, width))
-      .collect::<Vec<_>>();
-    let mut layer = pixels_into_bytes(layer);
-    layer.truncate(width * height * 4);
-    layer.shrink_to_fit();
-    // Layer's length is now equal to `width * height * 4`
+    // Volumes store every slice of a level in turn, and only the front one is decoded
+    let layer_data = &layer_data[..(layer_size / header.layer_depth(i)).min(layer_data.len())];
+
+    if !wants_layer(header, i, options) {
+      continue;
+    };
+
+    let unpadded = unpad_block_rows(header, i, layer_data, width, height, chunk_size);
+    let mut layer_data = &unpadded[..];
+    let layer_size = (w / 4) * (h / 4) * chunk_size;
+
+    if let Some(threshold) = options.verify_blocks {
+      verify_blocks(layer_data, chunk_size, threshold)?;
+    };
+
+    // Make up for missing bytes with zeros if asked to, so the blocks they cover decode to black
+    let padded;
+    if options.pad_truncated && layer_data.len() < layer_size {
+      padded = [layer_data, &vec![0; layer_size - layer_data.len()]].concat();
+      layer_data = &padded;
+    };
+
+    let detiled;
+    if let Some(mode) = options.detile {
+      detiled = detile_blocks(layer_data, w / 4, h / 4, chunk_size, mode)?;
+      layer_data = &detiled;
+    };
+
+    // Turn each block into 16 RGBA pixels, collected into the scratch buffer
+    // for the entire image. Follow here for the dirty details:
+    // https://www.khronos.org/opengl/wiki/S3_Texture_Compression
+    scratch.clear();
+    scratch.reserve(w * h);
+    for block in 0..layer_size / chunk_size {
+      let start = (block * chunk_size).min(layer_data.len());
+      let end = (start + chunk_size).min(layer_data.len());
+      dxt_chunk_transform(&layer_data[start..end], layout, options.ignore_alpha, &mut scratch)?;
+    };
+
+    // Since the 16 byte pixel blocks are actually 4x4 texels, group image
+    // into chunks of four rows each, and then transpose into a row of texels.
+    let mut layer = Vec::with_capacity(width * height);
+    for (row, chunk) in scratch.chunks(4 * w).enumerate() {
+      let rows = (height - row * 4).min(4);
+      dxt_transpose_texels(chunk, w, width, rows, &mut layer);
+    };
+    // Layer's length is now equal to `width * height`
     // `width` and `height` are now the buffer's real dimensions
-    let layer = RgbaImage::from_raw(width as u32, height as u32, layer)
-      .expect("error converting bytes to image buffer");
-    layers.push(layer);
+    layers.push(finish_layer(layer, width, height, options));
   };
 
-  layers
+  Ok(layers)
 }
 
-pub fn decode_layers(header: &Header, buf: &[u8]) -> Result<Vec<RgbaImage>, Compression> {
-  match header.compression {
-    Compression::None => {
-      Ok(decode_layers_uncompressed(&header, buf))
-    },
-    Compression::DXT1 | Compression::DXT2 | Compression::DXT3 | Compression::DXT4 | Compression::DXT5 => {
-      Ok(decode_layers_dxt(&header, buf))
+// Returns the total number of bytes the payload of a file should contain
+pub fn payload_size(header: &Header) -> Result<usize, DecodeError> {
+  Ok(Layout::from_header(header)?.layer_byte_sizes(header).into_iter().sum())
+}
+
+// Picks the DXT layout whose size exactly matches the payload, for files with an unrecognized four-cc
+fn guess_dxt_layout(header: &Header, payload_len: usize) -> Option<Layout> {
+  [Layout::Dxt1 { alpha: false }, Layout::Dxt5].iter().copied()
+    .find(|layout| layout.layer_byte_sizes(header).into_iter().sum::<usize>() == payload_len)
+}
+
+// Only block compressed layouts can be detiled, the blocks being what gets shuffled around
+fn check_tiling(layout: Layout, options: &DecodeOptions) -> Result<(), DecodeError> {
+  match options.detile {
+    Some(mode) if layout.block_bytes().is_none() => Err(DecodeError::UnsupportedTiling(mode)),
+    _ => Ok(())
+  }
+}
+
+pub fn decode_layers(header: &Header, buf: &[u8], options: &DecodeOptions) -> Result<Vec<RgbaImage>, DecodeError> {
+  let layout = match Layout::from_header(header) {
+    Err(DecodeError::UnsupportedCompression(compression @ Compression::Other(_))) if options.guess_unknown => {
+      guess_dxt_layout(header, buf.len()).ok_or(DecodeError::UnsupportedCompression(compression))?
     },
-    compression => Err(compression)
+    layout => layout?
+  };
+
+  let layout = match layout {
+    Layout::Dxt5 if options.dxt5nm => Layout::Dxt5nm,
+    layout => layout
+  };
+
+  check_tiling(layout, options)?;
+  match layout {
+    Layout::Uncompressed => decode_layers_uncompressed(header, buf, options),
+    Layout::Float { channels, half } => decode_layers_float(header, buf, channels, half, options),
+    Layout::Snorm { channels } => decode_layers_snorm(header, buf, channels, options),
+    Layout::Int16 { channels, signed } => decode_layers_int16(header, buf, channels, signed, options),
+    Layout::Yuv422 { uyvy } => decode_layers_yuv422(header, buf, uyvy, options),
+    layout => decode_layers_dxt(header, buf, layout, options)
   }
 }
 
+// Multiplies each color channel by the pixel's alpha
+fn premultiply(pixel: &mut [u8; 4]) {
+  let alpha = pixel[3] as u32;
+  for channel in &mut pixel[..3] {
+    *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+  };
+}
+
+// Divides the color channels by alpha, the inverse of `premultiply`. Fully transparent
+// pixels have lost their color entirely, so they are left as they are.
+pub fn unpremultiply(pixel: &mut [u8; 4]) {
+  let alpha = pixel[3] as u32;
+  if alpha == 0 {
+    return;
+  };
+
+  for channel in &mut pixel[..3] {
+    *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+  };
+}
+
+// Takes each channel of the pixel from where the swizzle says
+fn swizzle(pixel: &mut [u8; 4], swizzle: [Channel; 4]) {
+  let source = *pixel;
+  *pixel = swizzle.map(|channel| match channel {
+    Channel::Red => source[0],
+    Channel::Green => source[1],
+    Channel::Blue => source[2],
+    Channel::Alpha => source[3],
+    Channel::Zero => 0,
+    Channel::One => 255
+  });
+}
+
+// Applies any post-processing requested in the options, then converts the pixels into an image
+fn finish_layer(mut pixels: Vec<[u8; 4]>, width: usize, height: usize, options: &DecodeOptions) -> RgbaImage {
+  // DXT5 and uncompressed layers skip their alpha while decoding, but other formats still need it cleared
+  if options.ignore_alpha {
+    pixels.iter_mut().for_each(|pixel| pixel[3] = 255);
+  };
+
+  if options.swizzle != Channel::IDENTITY {
+    pixels.iter_mut().for_each(|pixel| swizzle(pixel, options.swizzle));
+  };
+
+  if let Some(key) = options.color_key {
+    pixels.iter_mut()
+      .filter(|pixel| pixel[..3] == key)
+      .for_each(|pixel| pixel[3] = 0);
+  };
+
+  if options.unpremultiply {
+    pixels.iter_mut().for_each(unpremultiply);
+  };
+
+  if options.premultiply {
+    pixels.iter_mut().for_each(premultiply);
+  };
+
+  if options.output_order == ChannelOrder::Bgra {
+    pixels.iter_mut().for_each(|pixel| pixel.swap(0, 2));
+  };
+
+  let mut bytes = pixels_into_bytes(pixels);
+  bytes.shrink_to_fit();
+  RgbaImage::from_raw(width as u32, height as u32, bytes)
+    .expect("error converting bytes to image buffer")
+}
+
 fn pixels_into_bytes(pixels: Vec<[u8; 4]>) -> Vec<u8> {
   use std::mem::ManuallyDrop;
   unsafe {
@@ -329,3 +1192,104 @@ fn pixels_into_bytes(pixels: Vec<[u8; 4]>) -> Vec<u8> {
     Vec::from_raw_parts(ptr, len, cap)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{dxt1_lookup, INFLATE_5, INFLATE_6};
+
+  // The division `dxt1_lookup` used for every channel of every texel before the tables replaced it
+  fn inflate_reference(c: u32, inflate_by: u32) -> u32 {
+    c * 255 / (2u32.pow(inflate_by) - 1)
+  }
+
+  // `dxt1_lookup` as it was before the tables, inflating both endpoints on each call
+  fn dxt1_lookup_reference(key: (bool, u8), c0: u32, c1: u32, inflate_by: u32) -> Option<u32> {
+    let c0 = inflate_reference(c0, inflate_by);
+    let c1 = inflate_reference(c1, inflate_by);
+
+    match key {
+      (true, 0) | (false, 0) => Some(c0),
+      (true, 1) | (false, 1) => Some(c1),
+      (true, 2) => Some((2 * c0 + c1) / 3),
+      (true, 3) => Some((c0 + 2 * c1) / 3),
+      (false, 2) => Some((c0 + c1) / 2),
+      (false, 3) => None,
+      _ => unreachable!()
+    }
+  }
+
+  #[test]
+  fn test_inflate_tables() {
+    for (c, &inflated) in INFLATE_5.iter().enumerate() {
+      assert_eq!(inflated, inflate_reference(c as u32, 5), "5-bit value {}", c);
+    };
+
+    for (c, &inflated) in INFLATE_6.iter().enumerate() {
+      assert_eq!(inflated, inflate_reference(c as u32, 6), "6-bit value {}", c);
+    };
+  }
+
+  #[test]
+  fn test_dxt1_lookup_matches_reference() {
+    for &key in [(true, 0), (true, 1), (true, 2), (true, 3), (false, 0), (false, 1), (false, 2), (false, 3)].iter() {
+      for c0 in 0..64 {
+        for c1 in 0..64 {
+          assert_eq!(dxt1_lookup(key, c0, c1, &INFLATE_6), dxt1_lookup_reference(key, c0, c1, 6));
+          if c0 < 32 && c1 < 32 {
+            assert_eq!(dxt1_lookup(key, c0, c1, &INFLATE_5), dxt1_lookup_reference(key, c0, c1, 5));
+          };
+        };
+      };
+    };
+  }
+
+  #[cfg(feature = "nightly")]
+  mod benches {
+    use super::*;
+    use test::{black_box, Bencher};
+
+    // Looks up the red, green and blue of all 16 texels of every DXT1 block in the buffer, summing them up
+    fn lookup_blocks<F: Fn((bool, u8), (u32, u32), (u32, u32), (u32, u32)) -> [Option<u32>; 3]>(buf: &[u8], lookup: F) -> u32 {
+      let mut sum = 0;
+      for block in buf.chunks_exact(8) {
+        let color0 = u16::from_le_bytes([block[0], block[1]]) as u32;
+        let color1 = u16::from_le_bytes([block[2], block[3]]) as u32;
+        let red = ((color0 & 0xF800) >> 11, (color1 & 0xF800) >> 11);
+        let green = ((color0 & 0x7E0) >> 5, (color1 & 0x7E0) >> 5);
+        let blue = (color0 & 0x1F, color1 & 0x1F);
+        for &code in block[4..].iter() {
+          for i in 0..4 {
+            let key = (color0 > color1, (code >> (i * 2)) & 0x3);
+            sum += lookup(key, red, green, blue).iter().map(|c| c.unwrap_or(0)).sum::<u32>();
+          };
+        };
+      };
+
+      sum
+    }
+
+    fn sample_blocks() -> Vec<u8> {
+      std::fs::read("./samples/dxt1.dds").expect("Couldn't find file!").split_off(128)
+    }
+
+    #[bench]
+    fn bench_dxt1_lookup_table(b: &mut Bencher) {
+      let buf = sample_blocks();
+      b.iter(|| lookup_blocks(black_box(&buf), |key, r, g, b| [
+        dxt1_lookup(key, r.0, r.1, &INFLATE_5),
+        dxt1_lookup(key, g.0, g.1, &INFLATE_6),
+        dxt1_lookup(key, b.0, b.1, &INFLATE_5)
+      ]));
+    }
+
+    #[bench]
+    fn bench_dxt1_lookup_formula(b: &mut Bencher) {
+      let buf = sample_blocks();
+      b.iter(|| lookup_blocks(black_box(&buf), |key, r, g, b| [
+        dxt1_lookup_reference(key, r.0, r.1, 5),
+        dxt1_lookup_reference(key, g.0, g.1, 6),
+        dxt1_lookup_reference(key, b.0, b.1, 5)
+      ]));
+    }
+  }
+}
@@ -23,22 +23,57 @@
 //! }
 //! ```
 
+// The table-driven DXT1 decoder is benchmarked against the formula it replaced, which needs libtest
+#![cfg_attr(all(test, feature = "nightly"), feature(test))]
+
 extern crate bincode;
 extern crate image;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 extern crate serde;
+#[cfg(all(test, feature = "nightly"))]
+extern crate test;
 extern crate thiserror;
 
+mod atlas;
+mod bits;
+mod compress;
+mod cubemap;
+mod decoder;
+mod dxgi;
+pub mod flags;
 mod format;
+mod metrics;
+mod serialize;
+mod volume;
 
-use bincode::ErrorKind as BincodeError;
-use image::RgbaImage;
+use bincode::{ErrorKind as BincodeError, Options};
+use image::{imageops, DynamicImage, GrayImage, ImageBuffer, LumaA, Luma, Pixel, Rgb, Rgba, RgbaImage};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
 
-use crate::format::decode_layers;
+use crate::compress::encode_image_dxt;
+use crate::flags::*;
+use crate::format::{decode_layer_dxt5_alpha, decode_layers, decode_layers_as, decode_layers_native, encode_layers_uncompressed, payload_size, reconstruct_z, uses_punch_through_alpha, unpad_block_rows, unpremultiply, Layout};
+
+pub use crate::format::iter_uncompressed_pixels;
+
+pub use crate::atlas::{pack_atlas, Rect};
+pub use crate::cubemap::{CubeFace, Cubemap, CubemapDecoder};
+pub use crate::decoder::DdsImageDecoder;
+pub use crate::dxgi::DxgiFormat;
+pub use crate::metrics::{image_psnr, image_ssim, DdsDiff, LayerDiff};
+pub use crate::volume::Volume;
 
 use std::fmt;
-use std::io::{self, Read, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "rayon")]
+use std::fs::File;
+#[cfg(feature = "rayon")]
+use std::io::BufReader;
+use std::path::Path;
 
 /// Represents an error encountered while decoding/parsing a DDS file.
 #[derive(Debug, Error)]
@@ -52,7 +87,76 @@ pub enum DecodeError {
   #[error("expected the file to start with `DDS `, got `{}` instead", String::from_utf8_lossy(.0))]
   InvalidMagicBytes([u8; 4]),
   #[error("compression mode {0} is unsupported")]
-  UnsupportedCompression(Compression)
+  UnsupportedCompression(Compression),
+  #[error("expected a format with a single channel")]
+  NotSingleChannel,
+  #[error("pixel format {0} is unsupported")]
+  UnsupportedPixelFormat(PixelFormat),
+  #[error("dxgi format {0} is unsupported")]
+  UnsupportedDxgiFormat(DxgiFormat),
+  #[error("dxgi format {0} stores its data in separate planes, which is unsupported")]
+  UnsupportedLayout(DxgiFormat),
+  #[error("expected the header to span {expected} bytes, but {consumed} bytes were read")]
+  HeaderSizeMismatch { expected: usize, consumed: usize },
+  #[error("expected the pixel format size to be 32, got {0}")]
+  InvalidPixelFormatSize(u32),
+  #[error("mipmap count {count} is larger than the maximum of {max} for the image's dimensions")]
+  InvalidMipmapCount { count: u32, max: u32 },
+  #[error("header is missing the required flags {0:#x}")]
+  MissingRequiredFlags(u32),
+  #[error("expected a {expected} byte payload for {compression}, found {found} bytes, which is the size of uncompressed pixels")]
+  PayloadFormatMismatch { compression: Compression, expected: usize, found: usize },
+  #[error("uncompressed pixel format has a bit count of 32, but no channel masks")]
+  MissingChannelMasks,
+  #[error("expected a cubemap with all six faces")]
+  NotCubemap,
+  #[error("expected a volume texture")]
+  NotVolume,
+  #[error("mipmap level {level} is out of range, the file has {count} levels")]
+  MipmapLevelOutOfRange { level: usize, count: usize },
+  #[error("expected a {expected} byte compressed block, but only {found} bytes were left")]
+  InvalidBlockLength { expected: usize, found: usize },
+  #[error("expected a {expected} byte layer of uncompressed pixels, but only {found} bytes were left")]
+  InvalidLayerLength { expected: usize, found: usize },
+  #[error("{suspicious} of {total} compressed blocks look corrupt")]
+  LikelyCorrupt { suspicious: usize, total: usize },
+  #[error("tile mode {0:?} only supports block compressed formats with a power of two number of blocks on each side")]
+  UnsupportedTiling(TileMode)
+}
+
+/// Represents a deviation from the DDS specification that is tolerated when decoding
+/// in lenient mode, and rejected in strict mode, or a guess the decoder had to make along the way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Diagnostic {
+  /// The pixel format's `size` field was not 32
+  InvalidPixelFormatSize(u32),
+  /// The mipmap count was larger than the number of levels it takes to reach 1x1, so it was capped
+  InvalidMipmapCount { count: u32, max: u32 },
+  /// Some of the flags every header must set were missing, given as the missing flags
+  MissingRequiredFlags(u32),
+  /// An uncompressed 32-bit pixel format had no channel masks, as written by some ancient exporters
+  /// that misplace the pixel format. The pixels were decoded as A8R8G8B8.
+  MissingChannelMasks,
+  /// The mipmap count was 0 while `DDSCAPS_MIPMAP` was set, so a full chain down to 1x1 was assumed,
+  /// given as its number of levels. Strict decoding accepts this.
+  ImpliedMipmapCount(u32),
+  /// Some DXT1 blocks used the transparent index of their three color mode, but the file isn't marked as
+  /// having alpha, so those texels were decoded as opaque black. Strict decoding accepts this.
+  OpaquePunchThroughAlpha
+}
+
+impl Diagnostic {
+  /// Converts the diagnostic into the error that strict decoding reports for it,
+  /// or `None` if strict decoding accepts it.
+  pub fn into_error(self) -> Option<DecodeError> {
+    match self {
+      Diagnostic::InvalidPixelFormatSize(size) => Some(DecodeError::InvalidPixelFormatSize(size)),
+      Diagnostic::InvalidMipmapCount { count, max } => Some(DecodeError::InvalidMipmapCount { count, max }),
+      Diagnostic::MissingRequiredFlags(flags) => Some(DecodeError::MissingRequiredFlags(flags)),
+      Diagnostic::MissingChannelMasks => Some(DecodeError::MissingChannelMasks),
+      Diagnostic::ImpliedMipmapCount(_) | Diagnostic::OpaquePunchThroughAlpha => None
+    }
+  }
 }
 
 /// Represents an error encountered while encoding.
@@ -63,7 +167,23 @@ pub enum EncodeError {
   #[error(transparent)]
   EncodeHeader(#[from] Box<BincodeError>),
   #[error("compression mode {0} is unsupported")]
-  UnsupportedCompression(Compression)
+  UnsupportedCompression(Compression),
+  #[error("pixel format {0} is unsupported")]
+  UnsupportedPixelFormat(PixelFormat),
+  #[error("cubemap faces must be non-empty and share dimensions and mipmap counts")]
+  MismatchedCubemapFaces,
+  #[error("expected mipmap level {level} to be {expected:?}, got {found:?}")]
+  InvalidMipmapDimensions { level: usize, expected: (u32, u32), found: (u32, u32) },
+  #[error("mipmap level {level} is out of range, the file has {count} levels")]
+  MipmapLevelOutOfRange { level: usize, count: usize },
+  #[error("expected a mipmap chain of {expected} levels, got {found}")]
+  InvalidMipmapCount { expected: usize, found: usize },
+  #[error("{0:?} textures are unsupported")]
+  UnsupportedTextureKind(TextureKind),
+  #[error(transparent)]
+  Decode(#[from] DecodeError),
+  #[error(transparent)]
+  Image(#[from] image::ImageError)
 }
 
 /// Pixel information as represented in the DDS file
@@ -86,40 +206,29 @@ pub struct RawPixelFormat {
 impl RawPixelFormat {
   // Parses some common pixel formats from the raw bit masks, for convenience
   fn to_pixel_format(&self) -> PixelFormat {
-    let RawPixelFormat {
-      rgb_bit_count: count,
-      red_bit_mask: r,
-      green_bit_mask: g,
-      blue_bit_mask: b,
-      alpha_bit_mask: a,
-      ..
-    } = self;
-
-    match (count, r, g, b, a) {
-      (16, 0x7C00, 0x3E0, 0x1F, 0x8000) => PixelFormat::A1R5G5B5,
-      (32, 0x3FF, 0xFFC00, 0x3FF00000, 0xC0000000) => PixelFormat::A2B10G10R10,
-      (32, 0x3FF00000, 0xFFC00, 0x3FF, 0xC0000000) => PixelFormat::A2R10G10B10,
-      (8, 0xF, 0x0, 0x0, 0xF0) => PixelFormat::A4L4,
-      (16, 0xF00, 0xF0, 0xF, 0xF000) => PixelFormat::A4R4G4B4,
-      (8, 0x0, 0x0, 0x0, 0xFF) => PixelFormat::A8,
-      (32, 0xFF, 0xFF00, 0xFF0000, 0xFF000000) => PixelFormat::A8B8G8R8,
-      (16, 0xFF, 0x0, 0x0, 0xFF00) => PixelFormat::A8L8,
-      (16, 0xE0, 0x1C, 0x3, 0xFF00) => PixelFormat::A8R3G3B2,
-      (32, 0xFF0000, 0xFF00, 0xFF, 0xFF000000) => PixelFormat::A8R8G8B8,
-      (32, 0xFFFF, 0xFFFF0000, 0x0, 0x0) => PixelFormat::G16R16,
-      (16, 0xFFFF, 0x0, 0x0, 0x0) => PixelFormat::L16,
-      (8, 0xFF, 0x0, 0x0, 0x0) => PixelFormat::L8,
-      (16, 0xF800, 0x7E0, 0x1F, 0x0) => PixelFormat::R5G6B5,
-      (24, 0xFF0000, 0xFF00, 0xFF, 0x0) => PixelFormat::R8G8B8,
-      (16, 0x7C00, 0x3E0, 0x1F, 0x0) => PixelFormat::X1R5G5B5,
-      (16, 0xF00, 0xF0, 0xF, 0x0) => PixelFormat::X4R4G4B4,
-      (32, 0xFF, 0xFF00, 0xFF0000, 0x0) => PixelFormat::X8B8G8R8,
-      (32, 0xFF0000, 0xFF00, 0xFF, 0x0) => PixelFormat::X8R8G8B8,
-      (_, _, _, _, _) => PixelFormat::Unknown
-    }
+    let masks = [self.red_bit_mask, self.green_bit_mask, self.blue_bit_mask, self.alpha_bit_mask];
+    PIXEL_FORMATS.iter()
+      .find(|&&(_, bit_count, format_masks)| bit_count == self.rgb_bit_count && format_masks == masks)
+      .map_or(PixelFormat::Unknown, |&(pixel_format, _, _)| pixel_format)
+  }
+
+  // Uncompressed 32-bit formats need masks to say where each channel is, but some ancient
+  // exporters overlap a reserved field with the pixel format and leave every mask cleared
+  fn is_missing_masks(&self) -> bool {
+    self.four_cc == [0; 4] && self.rgb_bit_count == 32 &&
+      [self.red_bit_mask, self.green_bit_mask, self.blue_bit_mask, self.alpha_bit_mask] == [0; 4]
   }
 }
 
+// The raw headers are a plain sequence of little-endian integers, so pin bincode to fixed width
+// integers without any length prefixes, whatever its defaults are. This keeps every field at the
+// offset the DDS specification gives it.
+fn bincode_options() -> impl Options {
+  bincode::DefaultOptions::new()
+    .with_fixint_encoding()
+    .with_little_endian()
+}
+
 /// Header as represented in the DDS file
 ///
 /// Direct translation of struct found here:
@@ -144,18 +253,21 @@ pub struct RawHeader {
 }
 
 impl RawHeader {
+  /// The number of bytes the header occupies in the file, not counting the magic bytes.
+  pub const SIZE: usize = 124;
+
   const fn new_uncompressed(height: u32, width: u32) -> RawHeader {
     RawHeader {
-      size: height * width * 4,
-      flags: 0,
+      size: 124,
+      flags: DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT,
       height,
       width,
-      pitch_or_linear_size: 0,
+      pitch_or_linear_size: row_pitch(width, 32) as u32,
       depth: 0,
       mipmap_count: 0,
       reserved: [0; 11],
       pixel_format: RawPixelFormat {
-        size: 0,
+        size: 32,
         flags: 0x41,
         four_cc: [0; 4],
         rgb_bit_count: 32,
@@ -164,7 +276,63 @@ impl RawHeader {
         blue_bit_mask: 0xFF0000,
         alpha_bit_mask: 0xFF000000
       },
-      caps: 0,
+      caps: DDSCAPS_TEXTURE,
+      caps2: 0,
+      caps3: 0,
+      caps4: 0,
+      reserved2: 0
+    }
+  }
+
+  const fn new_compressed(height: u32, width: u32, compression: Compression, linear_size: u32) -> RawHeader {
+    RawHeader {
+      flags: DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_LINEARSIZE | DDSD_PIXELFORMAT,
+      pitch_or_linear_size: linear_size,
+      pixel_format: RawPixelFormat {
+        size: 32,
+        flags: DDPF_FOURCC,
+        four_cc: compression.to_bytes(),
+        rgb_bit_count: 0,
+        red_bit_mask: 0,
+        green_bit_mask: 0,
+        blue_bit_mask: 0,
+        alpha_bit_mask: 0
+      },
+      ..RawHeader::new_uncompressed(height, width)
+    }
+  }
+
+  // Describes the main image of a file written by the encoder, either as RGBA pixels or as DXT blocks
+  fn new_encoded(height: u32, width: u32, compression: Compression) -> RawHeader {
+    match compression.surface_bytes(width, height) {
+      Some(linear_size) if compression != Compression::None => {
+        RawHeader::new_compressed(height, width, compression, linear_size as u32)
+      },
+      _ => RawHeader::new_uncompressed(height, width)
+    }
+  }
+
+  fn new_luminance(height: u32, width: u32) -> RawHeader {
+    RawHeader {
+      size: 124,
+      flags: DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT,
+      height,
+      width,
+      pitch_or_linear_size: width,
+      depth: 0,
+      mipmap_count: 0,
+      reserved: [0; 11],
+      pixel_format: RawPixelFormat {
+        size: 32,
+        flags: DDPF_LUMINANCE,
+        four_cc: [0; 4],
+        rgb_bit_count: 8,
+        red_bit_mask: 0xFF,
+        green_bit_mask: 0,
+        blue_bit_mask: 0,
+        alpha_bit_mask: 0
+      },
+      caps: DDSCAPS_TEXTURE,
       caps2: 0,
       caps3: 0,
       caps4: 0,
@@ -175,8 +343,6 @@ impl RawHeader {
   /// Parses the raw header from the image. Useful for getting information not contained
   /// in the normal parsed Header struct.
   pub fn decode<R: Read>(mut reader: R) -> Result<RawHeader, DecodeError> {
-    let mut header_buf = [0u8; 124];
-
     let mut magic_bytes_buf = [0; 4];
     reader.read_exact(&mut magic_bytes_buf)?;
 
@@ -185,14 +351,122 @@ impl RawHeader {
       return Err(DecodeError::InvalidMagicBytes(magic_bytes_buf));
     };
 
+    RawHeader::decode_headerless(reader)
+  }
+
+  /// Parses the raw header from a reader positioned just past the magic bytes,
+  /// or at the start of a file that is missing them.
+  pub fn decode_headerless<R: Read>(mut reader: R) -> Result<RawHeader, DecodeError> {
+    let mut header_buf = [0u8; RawHeader::SIZE];
     reader.read_exact(&mut header_buf)?;
 
-    Ok(bincode::deserialize(&header_buf)?)
+    Ok(bincode_options().deserialize(&header_buf)?)
+  }
+
+  /// Reads the `(width, height)` of the main image directly out of the start of a DDS file,
+  /// without deserializing the rest of the header. Returns `None` if the bytes are too short
+  /// or don't start with `DDS `.
+  pub fn peek_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.len() < 20 || !is_dds(bytes) {
+      return None;
+    };
+
+    let read_u32 = |offset: usize| {
+      u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    };
+
+    Some((read_u32(16), read_u32(12)))
+  }
+
+  /// Checks the header for deviations from the DDS specification. In strict mode the first
+  /// deviation found is returned as an error, otherwise all of them are returned.
+  pub fn validate(&self, strict: bool) -> Result<Vec<Diagnostic>, DecodeError> {
+    let mut diagnostics = Vec::new();
+    if self.pixel_format.size != 32 {
+      diagnostics.push(Diagnostic::InvalidPixelFormatSize(self.pixel_format.size));
+    };
+
+    let max = full_mipmap_count(self.width, self.height);
+    if self.mipmap_count > max {
+      diagnostics.push(Diagnostic::InvalidMipmapCount { count: self.mipmap_count, max });
+    } else if self.mipmap_count == 0 && self.caps & DDSCAPS_MIPMAP != 0 {
+      diagnostics.push(Diagnostic::ImpliedMipmapCount(max));
+    };
+
+    // Readers ignore these flags in practice, and plenty of writers leave some of them out
+    let missing = (DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT) & !self.flags;
+    if missing != 0 {
+      diagnostics.push(Diagnostic::MissingRequiredFlags(missing));
+    };
+
+    if self.pixel_format.is_missing_masks() {
+      diagnostics.push(Diagnostic::MissingChannelMasks);
+    };
+
+    match diagnostics.iter().find_map(|diagnostic| diagnostic.into_error()) {
+      Some(err) if strict => Err(err),
+      _ => Ok(diagnostics)
+    }
   }
 
   pub fn encode<W: Write>(&self, mut writer: W) -> Result<(), EncodeError> {
     writer.write_all(b"DDS ")?;
-    bincode::serialize_into(writer, self)
+    bincode_options().serialize_into(writer, self)
+      .map_err(From::from)
+  }
+
+  /// Returns the magic bytes followed by the serialized header, exactly as `encode` writes them.
+  pub fn to_bytes(&self) -> [u8; 128] {
+    let mut bytes = [0; 128];
+    bytes[..4].copy_from_slice(b"DDS ");
+    bincode_options().serialize_into(&mut bytes[4..], self)
+      .expect("a raw header always serializes to 124 bytes");
+    bytes
+  }
+}
+
+/// Returns the number of mipmap levels in a full chain for a `width` by `height` texture, counting the
+/// main image and halving the larger side until it reaches 1. This is `1 + floor(log2(max(width, height)))`,
+/// so a 256x256 texture has 9 levels. Empty textures are counted as having a single level.
+pub const fn full_mipmap_count(width: u32, height: u32) -> u32 {
+  let size = if width > height { width } else { height };
+  32 - if size > 1 { size } else { 1 }.leading_zeros()
+}
+
+// Returns the number of bytes in a row of uncompressed pixels, rounded up to a whole byte.
+// Both values may come straight from a file, so the product is taken in 64 bits where it can't overflow.
+const fn row_pitch(width: u32, bits_per_pixel: u32) -> usize {
+  (width as u64 * bits_per_pixel as u64).div_ceil(8) as usize
+}
+
+/// Extended header present when the four-cc is `DX10`
+///
+/// Direct translation of struct found here:
+/// <https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-header-dxt10>
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawHeaderDx10 {
+  pub dxgi_format: u32,
+  pub resource_dimension: u32,
+  pub misc_flag: u32,
+  pub array_size: u32,
+  pub misc_flags2: u32
+}
+
+impl RawHeaderDx10 {
+  /// The number of bytes the DX10 header occupies in the file.
+  pub const SIZE: usize = 20;
+
+  /// Parses the DX10 extended header, which immediately follows the raw header in the file.
+  pub fn decode<R: Read>(mut reader: R) -> Result<RawHeaderDx10, DecodeError> {
+    let mut header_buf = [0u8; RawHeaderDx10::SIZE];
+    reader.read_exact(&mut header_buf)?;
+
+    Ok(bincode_options().deserialize(&header_buf)?)
+  }
+
+  pub fn encode<W: Write>(&self, writer: W) -> Result<(), EncodeError> {
+    bincode_options().serialize_into(writer, self)
       .map_err(From::from)
   }
 }
@@ -201,7 +475,7 @@ impl RawHeader {
 ///
 /// See here for more information about the common formats:
 /// <https://msdn.microsoft.com/en-us/library/bb943991.aspx>
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum PixelFormat {
   A1R5G5B5,
   A2B10G10R10,
@@ -213,6 +487,7 @@ pub enum PixelFormat {
   A8L8,
   A8R3G3B2,
   A8R8G8B8,
+  B8G8R8,
   G16R16,
   L16,
   L8,
@@ -225,16 +500,60 @@ pub enum PixelFormat {
   X8R8G8B8
 }
 
+// The bit count and red, green, blue and alpha masks of each known pixel format
+const PIXEL_FORMATS: [(PixelFormat, u32, [u32; 4]); 20] = [
+  (PixelFormat::A1R5G5B5, 16, [0x7C00, 0x3E0, 0x1F, 0x8000]),
+  (PixelFormat::A2B10G10R10, 32, [0x3FF, 0xFFC00, 0x3FF00000, 0xC0000000]),
+  (PixelFormat::A2R10G10B10, 32, [0x3FF00000, 0xFFC00, 0x3FF, 0xC0000000]),
+  (PixelFormat::A4L4, 8, [0xF, 0x0, 0x0, 0xF0]),
+  (PixelFormat::A4R4G4B4, 16, [0xF00, 0xF0, 0xF, 0xF000]),
+  (PixelFormat::A8, 8, [0x0, 0x0, 0x0, 0xFF]),
+  (PixelFormat::A8B8G8R8, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000]),
+  (PixelFormat::A8L8, 16, [0xFF, 0x0, 0x0, 0xFF00]),
+  (PixelFormat::A8R3G3B2, 16, [0xE0, 0x1C, 0x3, 0xFF00]),
+  (PixelFormat::A8R8G8B8, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000]),
+  (PixelFormat::B8G8R8, 24, [0xFF, 0xFF00, 0xFF0000, 0x0]),
+  (PixelFormat::G16R16, 32, [0xFFFF, 0xFFFF0000, 0x0, 0x0]),
+  (PixelFormat::L16, 16, [0xFFFF, 0x0, 0x0, 0x0]),
+  (PixelFormat::L8, 8, [0xFF, 0x0, 0x0, 0x0]),
+  (PixelFormat::R5G6B5, 16, [0xF800, 0x7E0, 0x1F, 0x0]),
+  (PixelFormat::R8G8B8, 24, [0xFF0000, 0xFF00, 0xFF, 0x0]),
+  (PixelFormat::X1R5G5B5, 16, [0x7C00, 0x3E0, 0x1F, 0x0]),
+  (PixelFormat::X4R4G4B4, 16, [0xF00, 0xF0, 0xF, 0x0]),
+  (PixelFormat::X8B8G8R8, 32, [0xFF, 0xFF00, 0xFF0000, 0x0]),
+  (PixelFormat::X8R8G8B8, 32, [0xFF0000, 0xFF00, 0xFF, 0x0])
+];
+
+impl PixelFormat {
+  /// Returns the bit count and red, green, blue and alpha channel masks of the pixel format,
+  /// or `None` for `Unknown`.
+  pub fn masks(self) -> Option<(u32, [u32; 4])> {
+    PIXEL_FORMATS.iter()
+      .find(|&&(pixel_format, _, _)| pixel_format == self)
+      .map(|&(_, bit_count, masks)| (bit_count, masks))
+  }
+
+  // Luminance formats store a single gray value in the red channel mask
+  pub(crate) fn is_luminance(self) -> bool {
+    matches!(self, PixelFormat::A4L4 | PixelFormat::A8L8 | PixelFormat::L16 | PixelFormat::L8)
+  }
+}
+
 impl fmt::Display for PixelFormat {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "{:?}", self)
   }
 }
 
-/// Represents the compression format of a DDS file, aka the four-cc bytes.
+/// Represents the compression format of a DDS file, aka the four-cc bytes. Serializes as the name of
+/// the compression, such as `"DXT5"`. Unrecognized four-ccs serialize as their four characters, or as
+/// a number if they hold a numeric `D3DFORMAT` value instead.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Compression {
   DXT1,
+  /// Nonstandard four-cc for DXT1 with punch-through alpha always enabled. Since four-cc
+  /// codes are only four bytes long, the `DXT1A` name is stored as `DX1A`.
+  DXT1A,
   DXT2,
   DXT3,
   DXT4,
@@ -249,6 +568,7 @@ impl Compression {
     match &bytes {
       &[0, 0, 0, 0] => Compression::None,
       b"DXT1" => Compression::DXT1,
+      b"DX1A" => Compression::DXT1A,
       b"DXT2" => Compression::DXT2,
       b"DXT3" => Compression::DXT3,
       b"DXT4" => Compression::DXT4,
@@ -258,9 +578,10 @@ impl Compression {
     }
   }
 
-  pub fn to_bytes(self) -> [u8; 4] {
+  pub const fn to_bytes(self) -> [u8; 4] {
     match self {
       Compression::DXT1 => *b"DXT1",
+      Compression::DXT1A => *b"DX1A",
       Compression::DXT2 => *b"DXT2",
       Compression::DXT3 => *b"DXT3",
       Compression::DXT4 => *b"DXT4",
@@ -270,12 +591,45 @@ impl Compression {
       Compression::Other(bytes) => bytes
     }
   }
+
+  /// Returns the number of bytes a single `width` by `height` surface takes up with this compression.
+  /// Returns `None` if the size depends on more than the compression, as with `None`, `DX10` and `Other`.
+  pub fn surface_bytes(self, width: u32, height: u32) -> Option<usize> {
+    // Blocks cover 4x4 texels, and partial blocks at the edges are stored in full
+    let blocks = width.div_ceil(4) as usize * height.div_ceil(4) as usize;
+    match self {
+      Compression::DXT1 | Compression::DXT1A => Some(blocks * 8),
+      Compression::DXT2 | Compression::DXT3 | Compression::DXT4 | Compression::DXT5 => Some(blocks * 16),
+      Compression::DX10 | Compression::None | Compression::Other(_) => None
+    }
+  }
+
+  /// Returns the DXGI format a DX10 header would use for the same data. Block compressed four-ccs
+  /// map onto their `BC*_UNORM` format, DXT2 and DXT4 included, since DX10 headers signal premultiplied
+  /// alpha separately. Returns `None` for `None`, `DX10` and four-ccs with no DXGI counterpart.
+  pub fn to_dxgi(self) -> Option<DxgiFormat> {
+    match self {
+      Compression::DXT1 | Compression::DXT1A => Some(DxgiFormat::BC1_UNORM),
+      Compression::DXT2 | Compression::DXT3 => Some(DxgiFormat::BC2_UNORM),
+      Compression::DXT4 | Compression::DXT5 => Some(DxgiFormat::BC3_UNORM),
+      Compression::Other(four_cc) => match &four_cc {
+        b"ATI1" | b"BC4U" => Some(DxgiFormat::BC4_UNORM),
+        b"BC4S" => Some(DxgiFormat::BC4_SNORM),
+        b"ATI2" | b"BC5U" => Some(DxgiFormat::BC5_UNORM),
+        b"BC5S" => Some(DxgiFormat::BC5_SNORM),
+        b"YUY2" => Some(DxgiFormat::YUY2),
+        _ => None
+      },
+      Compression::DX10 | Compression::None => None
+    }
+  }
 }
 
 impl fmt::Display for Compression {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       Compression::DXT1 => write!(f, "DXT1"),
+      Compression::DXT1A => write!(f, "DXT1A"),
       Compression::DXT2 => write!(f, "DXT2"),
       Compression::DXT3 => write!(f, "DXT3"),
       Compression::DXT4 => write!(f, "DXT4"),
@@ -287,13 +641,54 @@ impl fmt::Display for Compression {
   }
 }
 
+/// Represents a parsed DX10 extended header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderDx10 {
+  /// The format of the pixel data
+  pub dxgi_format: DxgiFormat,
+  /// The dimensionality of the resource, 2 for 1D textures, 3 for 2D textures and 4 for 3D textures
+  pub resource_dimension: u32,
+  /// Miscellaneous resource flags, such as whether this is a cubemap
+  pub misc_flag: u32,
+  /// The number of textures in the array
+  pub array_size: u32,
+  /// Additional flags, such as the alpha mode
+  pub misc_flags2: u32
+}
+
+impl From<RawHeaderDx10> for HeaderDx10 {
+  fn from(raw_header: RawHeaderDx10) -> HeaderDx10 {
+    HeaderDx10 {
+      dxgi_format: DxgiFormat::from_u32(raw_header.dxgi_format),
+      resource_dimension: raw_header.resource_dimension,
+      misc_flag: raw_header.misc_flag,
+      array_size: raw_header.array_size,
+      misc_flags2: raw_header.misc_flags2
+    }
+  }
+}
+
+/// The color space of a file's colors, as far as its header says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+  /// Colors are stored linearly
+  Linear,
+  /// Colors are stored sRGB encoded
+  Srgb,
+  /// The header doesn't say, as with every file without a DX10 header. These are usually sRGB
+  /// if they hold colors, but may just as well hold linear data such as normals.
+  Unknown
+}
+
 /// Represents a parsed DDS header. Has several convenience attributes.
-#[derive(Debug, Clone, PartialEq, Eq,)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Header {
   /// Height of the main image
   pub height: u32,
   /// Width of the main image
   pub width: u32,
+  /// Number of slices in the main image of a volume texture, 1 for everything else
+  pub depth: u32,
   /// How many levels of mipmaps there are
   pub mipmap_count: u32,
   /// Compression type used
@@ -304,88 +699,1644 @@ pub struct Header {
   pub pixel_format: PixelFormat,
   /// The number of bytes used per-pixel
   pub pixel_bytes: usize,
+  /// The number of bits used per-pixel by uncompressed formats
+  pub bits_per_pixel: u32,
   /// The bit masks used for each channel
-  pub channel_masks: [u32; 4]
+  pub channel_masks: [u32; 4],
+  /// The number of bytes per row of the main image, if the header specifies one
+  pub pitch: Option<usize>,
+  /// The DX10 extended header, present when the compression is `DX10`
+  pub dx10: Option<HeaderDx10>,
+  /// The color space of the pixel data, known from the `_SRGB` suffix of DX10 formats
+  pub color_space: ColorSpace,
+  /// Flags describing the complexity of the surfaces in the file, see `DDSCAPS_*` in [`flags`]
+  pub caps: u32,
+  /// Flags describing cubemap faces and volumes, see `DDSCAPS2_*` in [`flags`]
+  pub caps2: u32,
+  /// Reserved header fields, which some tools use to store metadata
+  pub reserved: [u32; 11],
+  /// The final reserved header field
+  pub reserved2: u32
 }
 
 impl Header {
   /// Parses a `Header` object from a reader.
   pub fn decode<R: Read>(reader: R) -> Result<Header, DecodeError> {
-    let raw_header = RawHeader::decode(reader)?;
+    Header::decode_with(reader, &DecodeOptions::default())
+  }
+
+  /// Parses a `Header` object from a reader, rejecting malformed headers if `options.strict` is set.
+  pub fn decode_with<R: Read>(mut reader: R, options: &DecodeOptions) -> Result<Header, DecodeError> {
+    let raw_header = RawHeader::decode(&mut reader)?;
+    Ok(Header::from_raw_header(raw_header, reader, options)?.0)
+  }
+
+  // Builds the parsed header from a raw header, reading the DX10 header that follows it if there is one.
+  // Also returns the deviations from the specification `RawHeader::validate` tolerated.
+  fn from_raw_header<R: Read>(raw_header: RawHeader, mut reader: R, options: &DecodeOptions) -> Result<(Header, Vec<Diagnostic>), DecodeError> {
+    let diagnostics = raw_header.validate(options.strict)?;
+    let compression = Compression::from_bytes(raw_header.pixel_format.four_cc);
+
+    let dx10 = match compression {
+      Compression::DX10 => Some(HeaderDx10::from(RawHeaderDx10::decode(&mut reader)?)),
+      _ => None
+    };
+
+    // Uncompressed DXGI formats are described with the same bit masks legacy files use
+    let mut pixel_format = raw_header.pixel_format.clone();
+    if let Some((bit_count, masks)) = dx10.and_then(|dx10| dx10.dxgi_format.uncompressed_masks()) {
+      pixel_format.rgb_bit_count = bit_count;
+      pixel_format.red_bit_mask = masks[0];
+      pixel_format.green_bit_mask = masks[1];
+      pixel_format.blue_bit_mask = masks[2];
+      pixel_format.alpha_bit_mask = masks[3];
+    };
+
+    // A8R8G8B8 is by far the most common 32-bit format, so it's the best guess for a format
+    // without masks. Strict decoding has already rejected these in `validate`.
+    if pixel_format.is_missing_masks() {
+      let (_, masks) = PixelFormat::A8R8G8B8.masks().expect("A8R8G8B8 has masks");
+      pixel_format.red_bit_mask = masks[0];
+      pixel_format.green_bit_mask = masks[1];
+      pixel_format.blue_bit_mask = masks[2];
+      pixel_format.alpha_bit_mask = masks[3];
+    };
+
+    // The resource dimension settles whether a DX10 file is a volume, since its depth might otherwise
+    // be confused with an array size. Older files sometimes give a depth without the volume cap.
+    let volume = match dx10 {
+      Some(dx10) => dx10.resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D,
+      None => raw_header.caps2 & DDSCAPS2_VOLUME != 0 || (raw_header.flags & DDSD_DEPTH != 0 && raw_header.depth > 1)
+    };
+
+    // DX10 cubemaps are flagged in the misc flags, and always have every face. Writers like texconv
+    // set the legacy cubemap caps as well, but not all of them do, so the caps are filled in here.
+    let cubemap = dx10.is_some_and(|dx10| dx10.misc_flag & D3D10_RESOURCE_MISC_TEXTURECUBE != 0);
 
-    Ok(Header {
+    let header = Header {
       height: raw_header.height,
       width: raw_header.width,
+      depth: match volume {
+        true => raw_header.depth.max(1),
+        false => 1
+      },
       mipmap_count: raw_header.mipmap_count,
-      compression: Compression::from_bytes(raw_header.pixel_format.four_cc),
-      fourcc: raw_header.pixel_format.four_cc,
-      pixel_format: raw_header.pixel_format.to_pixel_format(),
-      pixel_bytes: raw_header.pixel_format.rgb_bit_count as usize / 8,
+      compression,
+      fourcc: pixel_format.four_cc,
+      pixel_format: pixel_format.to_pixel_format(),
+      pixel_bytes: pixel_format.rgb_bit_count as usize / 8,
+      bits_per_pixel: pixel_format.rgb_bit_count,
       channel_masks: [
-        raw_header.pixel_format.red_bit_mask,
-        raw_header.pixel_format.green_bit_mask,
-        raw_header.pixel_format.blue_bit_mask,
-        raw_header.pixel_format.alpha_bit_mask
-      ]
-    })
+        pixel_format.red_bit_mask,
+        pixel_format.green_bit_mask,
+        pixel_format.blue_bit_mask,
+        pixel_format.alpha_bit_mask
+      ],
+      pitch: match raw_header.flags & DDSD_PITCH {
+        0 => None,
+        _ => Some(raw_header.pitch_or_linear_size as usize)
+      },
+      color_space: match dx10 {
+        Some(dx10) if dx10.dxgi_format.is_srgb() => ColorSpace::Srgb,
+        // Typeless formats leave it up to whoever uses the texture
+        Some(dx10) if dx10.dxgi_format.typeless_to_unorm() != dx10.dxgi_format => ColorSpace::Unknown,
+        Some(_) => ColorSpace::Linear,
+        None => ColorSpace::Unknown
+      },
+      dx10,
+      caps: raw_header.caps,
+      caps2: match (volume, cubemap) {
+        (true, _) => raw_header.caps2 | DDSCAPS2_VOLUME,
+        (false, true) => raw_header.caps2 & !DDSCAPS2_VOLUME | DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES,
+        (false, false) => raw_header.caps2 & !DDSCAPS2_VOLUME
+      },
+      reserved: raw_header.reserved,
+      reserved2: raw_header.reserved2
+    };
+
+    Ok((header, diagnostics))
+  }
+
+  /// The number of bytes the magic bytes, header and DX10 header (if present) occupy at the
+  /// start of the file. This is where the pixel data begins.
+  pub fn size(&self) -> usize {
+    match self.dx10 {
+      Some(_) => 4 + RawHeader::SIZE + RawHeaderDx10::SIZE,
+      None => 4 + RawHeader::SIZE
+    }
+  }
+
+  /// Builds a raw header describing this header, suitable for encoding.
+  pub fn to_raw_header(&self) -> RawHeader {
+    // The complexity caps are derived from the texture itself, so stale ones never carry over
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    let mut caps = (self.caps & !(DDSCAPS_COMPLEX | DDSCAPS_MIPMAP)) | DDSCAPS_TEXTURE;
+    if self.mipmap_count > 1 {
+      flags |= DDSD_MIPMAPCOUNT;
+      caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    };
+
+    if self.is_cubemap() || self.is_volume() {
+      caps |= DDSCAPS_COMPLEX;
+    };
+
+    if self.is_volume() {
+      flags |= DDSD_DEPTH;
+    };
+
+    if self.pitch.is_some() {
+      flags |= DDSD_PITCH;
+    };
+
+    // Compressed formats are identified by their four-cc alone, while uncompressed
+    // formats need their bit count and masks
+    let pixel_format = match self.compression {
+      Compression::None => {
+        let [r, g, b, a] = self.channel_masks;
+        let pixel_format_flags = match (self.pixel_format, a) {
+          (PixelFormat::A8, _) => DDPF_ALPHA,
+          (PixelFormat::L8, _) | (PixelFormat::L16, _) => DDPF_LUMINANCE,
+          (PixelFormat::A8L8, _) | (PixelFormat::A4L4, _) => DDPF_LUMINANCE | DDPF_ALPHAPIXELS,
+          (_, 0) => DDPF_RGB,
+          (_, _) => DDPF_RGB | DDPF_ALPHAPIXELS
+        };
+
+        RawPixelFormat {
+          size: 32,
+          flags: pixel_format_flags,
+          four_cc: [0; 4],
+          rgb_bit_count: self.bits_per_pixel,
+          red_bit_mask: r,
+          green_bit_mask: g,
+          blue_bit_mask: b,
+          alpha_bit_mask: a
+        }
+      },
+      compression => RawPixelFormat {
+        size: 32,
+        flags: DDPF_FOURCC,
+        four_cc: compression.to_bytes(),
+        rgb_bit_count: 0,
+        red_bit_mask: 0,
+        green_bit_mask: 0,
+        blue_bit_mask: 0,
+        alpha_bit_mask: 0
+      }
+    };
+
+    RawHeader {
+      size: 124,
+      flags,
+      height: self.height,
+      width: self.width,
+      pitch_or_linear_size: self.pitch.unwrap_or(0) as u32,
+      depth: if self.is_volume() { self.depth } else { 0 },
+      mipmap_count: self.mipmap_count,
+      reserved: self.reserved,
+      pixel_format,
+      caps,
+      caps2: self.caps2,
+      caps3: 0,
+      caps4: 0,
+      reserved2: self.reserved2
+    }
+  }
+
+  /// Returns the number of bytes in each row of the main image when tightly packed. For uncompressed
+  /// formats this is `(width * bits_per_pixel + 7) / 8`, as given by the DDS specification, while
+  /// block compressed formats count the bytes in each row of 4x4 blocks.
+  pub fn row_pitch(&self) -> usize {
+    let width = self.width as usize;
+    match Layout::from_header(self) {
+      Ok(layout @ Layout::Dxt1 { .. }) | Ok(layout @ Layout::Dxt3) |
+      Ok(layout @ Layout::Dxt5) | Ok(layout @ Layout::Dxt5nm) | Ok(layout @ Layout::Bc4) |
+      Ok(layout @ Layout::Bc5) => {
+        width.div_ceil(4).max(1) * layout.block_bytes().unwrap_or(0)
+      },
+      Ok(Layout::Float { channels, half }) => width * channels * if half { 2 } else { 4 },
+      Ok(Layout::Snorm { channels }) => width * channels,
+      Ok(Layout::Int16 { channels, .. }) => width * channels * 2,
+      Ok(Layout::Yuv422 { .. }) => width.div_ceil(2) * 4,
+      Ok(Layout::Uncompressed) | Err(_) => row_pitch(self.width, self.bits_per_pixel)
+    }
+  }
+
+  /// Returns a one-line human readable summary of the header, such as `256x256 DXT5 (BC3) mips=9 alpha`,
+  /// combining the dimensions, format, mipmap count and whether there's an alpha channel.
+  pub fn summary(&self) -> String {
+    let format = match (self.compression, self.dx10) {
+      (Compression::None, _) => self.pixel_format.to_string(),
+      (Compression::DXT1, _) | (Compression::DXT1A, _) => format!("{} (BC1)", self.compression),
+      (Compression::DXT2, _) | (Compression::DXT3, _) => format!("{} (BC2)", self.compression),
+      (Compression::DXT4, _) | (Compression::DXT5, _) => format!("{} (BC3)", self.compression),
+      (Compression::DX10, Some(dx10)) => format!("DX10 ({})", dx10.dxgi_format),
+      (compression, _) => compression.to_string()
+    };
+
+    let mut summary = format!("{}x{} {} mips={}", self.width, self.height, format, self.mipmap_levels());
+    if self.is_cubemap() {
+      summary.push_str(" cubemap");
+    };
+
+    summary.push_str(if self.has_alpha() { " alpha" } else { " opaque" });
+    summary
+  }
+
+  /// Returns the `(offset, size)` in bytes of every layer in the payload, in file order.
+  /// Offsets are relative to the start of the payload, see [`Header::size`].
+  /// Fails if the format isn't supported by the decoder.
+  pub fn layer_offsets(&self) -> Result<Vec<(usize, usize)>, DecodeError> {
+    let mut offset = 0;
+    let offsets = Layout::from_header(self)?.layer_byte_sizes(self).into_iter()
+      .map(|size| {
+        offset += size;
+        (offset - size, size)
+      })
+      .collect();
+    Ok(offsets)
+  }
+
+  /// Returns the signature NVIDIA Texture Tools leaves in `reserved[9]` to identify files it wrote,
+  /// which is always `NVTT`. `reserved[10]` then holds the version of the tool. Returns `None` for
+  /// files written by anything else.
+  pub fn tool_signature(&self) -> Option<[u8; 4]> {
+    match self.reserved[9].to_le_bytes() {
+      signature @ [b'N', b'V', b'T', b'T'] => Some(signature),
+      _ => None
+    }
+  }
+
+  /// Guesses which tool wrote the file from the signatures some tools leave in the first reserved header
+  /// fields, which can help decide how to interpret channel conventions such as those of normal maps.
+  /// NVTT is also recognized by the signature newer versions leave in `reserved[9]`, see [`Header::tool_signature`].
+  /// The reserved fields are kept when re-encoding a decoded file, so the signatures survive a round trip.
+  pub fn creator_hint(&self) -> CreatorHint {
+    let words = [self.reserved[0].to_le_bytes(), self.reserved[1].to_le_bytes()];
+    if words[0] == *b"NVTT" || self.tool_signature().is_some() {
+      CreatorHint::Nvtt
+    } else if words == [*b"TEXC", *b"ONV\0"] {
+      CreatorHint::Texconv
+    } else {
+      CreatorHint::Unknown
+    }
+  }
+
+  /// Returns the number of bits used by the red, green, blue and alpha channels, derived from
+  /// the channel masks. Compressed formats have no channel masks, so they report zero for each.
+  pub fn channel_bits(&self) -> [u8; 4] {
+    self.channel_masks.map(|mask| mask.count_ones() as u8)
+  }
+
+  /// Returns true if the pixel format has an alpha channel. Formats the decoder doesn't support report false.
+  pub fn has_alpha(&self) -> bool {
+    match Layout::from_header(self) {
+      Ok(Layout::Uncompressed) => self.channel_masks[3] != 0,
+      Ok(Layout::Float { channels, .. }) | Ok(Layout::Snorm { channels }) |
+      Ok(Layout::Int16 { channels, .. }) => channels == 4,
+      Ok(Layout::Dxt1 { alpha }) => alpha,
+      Ok(Layout::Dxt3) | Ok(Layout::Dxt5) => true,
+      Ok(Layout::Dxt5nm) | Ok(Layout::Bc4) | Ok(Layout::Bc5) | Ok(Layout::Yuv422 { .. }) | Err(_) => false
+    }
+  }
+
+  // Returns which channel of the decoded images holds the value of a single channel format.
+  // BC4 and luminance formats keep their value in red, while A8 only has alpha.
+  fn gray_channel(&self) -> Option<usize> {
+    match (Layout::from_header(self), self.channel_masks) {
+      (Ok(Layout::Bc4), _) => Some(0),
+      (Ok(Layout::Uncompressed), masks) if masks.iter().filter(|&&mask| mask != 0).count() == 1 => {
+        masks.iter().position(|&mask| mask != 0)
+      },
+      _ => None
+    }
+  }
+
+  /// Returns true if the file is a cubemap, in which case each face is stored with its own mipmap chain.
+  /// Both the legacy `DDSCAPS2_CUBEMAP` cap and the DX10 `D3D10_RESOURCE_MISC_TEXTURECUBE` flag are recognized.
+  pub fn is_cubemap(&self) -> bool {
+    self.caps2 & DDSCAPS2_CUBEMAP != 0
+  }
+
+  /// Returns true if the color channels are stored premultiplied by alpha. This is the case for
+  /// DXT2 and DXT4, and for DX10 files whose alpha mode is `DDS_ALPHA_MODE_PREMULTIPLIED`.
+  pub fn is_premultiplied(&self) -> bool {
+    match (self.compression, self.dx10) {
+      (Compression::DXT2, _) | (Compression::DXT4, _) => true,
+      (_, Some(dx10)) => dx10.misc_flags2 & DDS_ALPHA_MODE_MASK == DDS_ALPHA_MODE_PREMULTIPLIED,
+      _ => false
+    }
+  }
+
+  /// Returns true if the file is a volume texture, made up of `depth` slices that are halved along with
+  /// the width and height at each mipmap level. See [`Volume`] for decoding them.
+  pub fn is_volume(&self) -> bool {
+    self.caps2 & DDSCAPS2_VOLUME != 0
   }
 
-  // Returns layer sizes
+  /// Classifies the file as a 2D texture, cubemap, volume texture or texture array. Volumes are told
+  /// apart from arrays by the DX10 resource dimension, or by `DDSCAPS2_VOLUME` and `DDSD_DEPTH` in
+  /// files without a DX10 header. Arrays of cubemaps are classified as cubemaps.
+  pub fn texture_kind(&self) -> TextureKind {
+    if self.is_volume() {
+      TextureKind::Volume
+    } else if self.is_cubemap() {
+      TextureKind::Cubemap
+    } else if self.array_size() > 1 {
+      TextureKind::Array
+    } else {
+      TextureKind::Texture2D
+    }
+  }
+
+  /// Returns the number of slices in a mipmap level of a volume texture, which halves at each level
+  /// until it reaches 1. Textures other than volumes always have a single slice.
+  pub fn level_depth(&self, level: usize) -> u32 {
+    (self.depth >> level).max(1)
+  }
+
+  // Returns the number of slices of the mipmap level a layer belongs to, see `level_depth`
+  fn layer_depth(&self, layer: usize) -> usize {
+    self.level_depth(layer % self.mipmap_levels() as usize) as usize
+  }
+
+  // Returns how many faces are stored in the file, cubemaps may leave some of them out
+  fn face_count(&self) -> u32 {
+    match self.is_cubemap() {
+      true => (self.caps2 & DDSCAPS2_CUBEMAP_ALLFACES).count_ones(),
+      false => 1
+    }
+  }
+
+  /// Returns the number of textures in a DX10 texture array, each with its own mipmap chain.
+  /// Files without a DX10 header always hold a single texture. For arrays of cubemaps this is the
+  /// number of cubemaps, which the DX10 header gives directly. Some writers count the faces instead,
+  /// so an array size that is a multiple of 6 is taken to be six faces per cubemap.
+  pub fn array_size(&self) -> u32 {
+    match self.dx10 {
+      Some(dx10) if self.is_cubemap() && dx10.array_size % 6 == 0 => (dx10.array_size / 6).max(1),
+      Some(dx10) => dx10.array_size.max(1),
+      None => 1
+    }
+  }
+
+  // Describes a single mipmap level on its own, as if it were the main image of a 2D texture
+  fn level_header(&self, level: usize) -> Header {
+    Header {
+      height: (self.height >> level).max(1),
+      width: (self.width >> level).max(1),
+      depth: 1,
+      mipmap_count: 1,
+      pitch: self.pitch.filter(|_| level == 0),
+      caps: self.caps & !(DDSCAPS_COMPLEX | DDSCAPS_MIPMAP),
+      caps2: 0,
+      ..self.clone()
+    }
+  }
+
+  // Returns the sizes of every layer in the file, repeating the mipmap chain for each face and array slice
   fn get_layer_sizes(&self) -> Vec<(usize, usize)> {
-    // Files with only a single texture will often have
-    // the mipmap count set to 0, so we force generating
-    // at least a single level
-    let count: u32 = self.mipmap_count.max(1);
-    let mut layers = Vec::with_capacity(count as usize);
-    for i in 0..count {
-      layers.push(((self.height / 2u32.pow(i)) as usize, (self.width / 2u32.pow(i)) as usize));
+    let count = self.mipmap_levels();
+    let surfaces = self.face_count() * self.array_size();
+    let mut layers = Vec::with_capacity((count * surfaces) as usize);
+    for _ in 0..surfaces {
+      for i in 0..count {
+        let height = (self.height >> i).max(1);
+        let width = (self.width >> i).max(1);
+        layers.push((height as usize, width as usize));
+      };
     };
 
     layers
   }
+
+  // Returns how many mipmap levels are stored in the file
+  fn mipmap_levels(&self) -> u32 {
+    let full = full_mipmap_count(self.width, self.height);
+    match self.mipmap_count {
+      // Some files flag a mipmap chain without saying how long it is,
+      // in which case the chain goes all the way down to 1x1
+      0 if self.caps & DDSCAPS_MIPMAP != 0 => full,
+      // Files with only a single texture will often have
+      // the mipmap count set to 0, so we force generating
+      // at least a single level. Levels past 1x1 can't exist,
+      // so overlarge counts are capped to the full chain.
+      count => count.clamp(1, full)
+    }
+  }
 }
 
-/// Represents a parsed DDS file
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Dds {
-  /// The parsed DDS header
-  pub header: Header,
-  /// Mipmap layers
-  pub layers: Vec<RgbaImage>
+/// The tool that wrote a DDS file, as far as the signatures in its reserved header fields tell.
+/// See [`Header::creator_hint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CreatorHint {
+  /// NVIDIA Texture Tools, signed with `NVTT` in `reserved[0]` or `reserved[9]`
+  Nvtt,
+  /// DirectXTex's texconv, recognized by a null terminated `TEXCONV` across `reserved[0]` and `reserved[1]`
+  Texconv,
+  /// No known signature, as many tools leave the reserved fields zeroed
+  Unknown
 }
 
-impl Dds {
-  /// Decodes a buffer into a header and a series of mipmap images.
-  /// Handles uncompressed and DXT1-5 compressed images.
-  pub fn decode<R: Read>(mut reader: R) -> Result<Dds, DecodeError> {
-    let header = Header::decode(&mut reader)?;
+/// The kind of texture a DDS file holds, see [`Header::texture_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+  /// A single 2D texture, with its mipmaps
+  Texture2D,
+  /// Up to six 2D faces, each with its own mipmap chain
+  Cubemap,
+  /// A stack of 2D slices, with the number of slices halving at each mipmap level
+  Volume,
+  /// A DX10 texture array, with a mipmap chain for each element of the array
+  Array
+}
 
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
+/// An RGBA image with 16 bits per channel.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
 
-    let layers = decode_layers(&header, &buf)
-      .map_err(DecodeError::UnsupportedCompression)?;
+/// An RGBA image with a 32-bit float per channel.
+pub type Rgba32FImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
 
-    Ok(Dds { header, layers })
+/// An image of any [`DecodedPixel`] type.
+pub type DecodedImage<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// A pixel type that mipmap layers can be decoded into, see [`Dds::decode_as`]. Each method
+/// converts a pixel decoded at one of the native precisions of [`NativeDds`] into this type.
+pub trait DecodedPixel: Pixel + 'static {
+  fn from_u8(pixel: [u8; 4]) -> Self;
+  fn from_u16(pixel: [u16; 4]) -> Self;
+  fn from_f32(pixel: [f32; 4]) -> Self;
+}
+
+impl DecodedPixel for Rgba<u8> {
+  fn from_u8(pixel: [u8; 4]) -> Self {
+    Rgba(pixel)
   }
 
-  /// Encodes an RGBA image as an uncompressed A8R8G8B8 DDS.
-  pub fn encode_uncompressed<W: Write>(mut writer: W, image: &RgbaImage) -> Result<(), EncodeError> {
-    let (width, height) = image.dimensions();
-    RawHeader::new_uncompressed(height, width).encode(&mut writer)?;
+  fn from_u16(pixel: [u16; 4]) -> Self {
+    Rgba(pixel.map(|value| ((value as u32 * 255 + 32767) / 65535) as u8))
+  }
 
-    let data: &[u8] = image.as_raw();
-    writer.write_all(data)?;
+  fn from_f32(pixel: [f32; 4]) -> Self {
+    Rgba(pixel.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+  }
+}
 
-    Ok(())
+impl DecodedPixel for Rgba<u16> {
+  fn from_u8(pixel: [u8; 4]) -> Self {
+    Rgba(pixel.map(|value| value as u16 * 257))
   }
 
-  /// Encodes a series of Pixels as a bunch of bytes, suitable for writing to disk, etc.
-  /// Currently only supports uncompressed RGBA images.
-  pub fn encode<W: Write>(writer: W, image: &RgbaImage, compression: Compression) -> Result<(), EncodeError> {
-    match compression {
-      Compression::None => Dds::encode_uncompressed(writer, image),
-      compression => Err(EncodeError::UnsupportedCompression(compression))
-    }
+  fn from_u16(pixel: [u16; 4]) -> Self {
+    Rgba(pixel)
+  }
+
+  fn from_f32(pixel: [f32; 4]) -> Self {
+    Rgba(pixel.map(|value| (value.clamp(0.0, 1.0) * 65535.0).round() as u16))
+  }
+}
+
+impl DecodedPixel for Rgba<f32> {
+  fn from_u8(pixel: [u8; 4]) -> Self {
+    Rgba(pixel.map(|value| value as f32 / 255.0))
+  }
+
+  fn from_u16(pixel: [u16; 4]) -> Self {
+    Rgba(pixel.map(|value| value as f32 / 65535.0))
+  }
+
+  fn from_f32(pixel: [f32; 4]) -> Self {
+    Rgba(pixel)
+  }
+}
+
+/// Mipmap layers decoded at the native precision of the file's format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeDds {
+  /// Formats with at most 8 bits per channel
+  U8(Vec<RgbaImage>),
+  /// Formats with more than 8 bits in any channel, such as A2B10G10R10, G16R16 and L16
+  U16(Vec<Rgba16Image>),
+  /// Floating point formats, and signed normalized formats with each channel in the range `[-1, 1]`
+  F32(Vec<Rgba32FImage>)
+}
+
+/// The order in which channels are stored in decoded images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChannelOrder {
+  #[default]
+  Rgba,
+  Bgra
+}
+
+/// A source for one channel of a swizzled pixel, see [`DecodeOptions::swizzle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+  Red,
+  Green,
+  Blue,
+  Alpha,
+  /// The channel is always 0
+  Zero,
+  /// The channel is always 255
+  One
+}
+
+impl Channel {
+  /// The swizzle that leaves every channel where it is.
+  pub const IDENTITY: [Channel; 4] = [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha];
+}
+
+/// The order the blocks of a tiled texture are stored in, see [`DecodeOptions::detile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TileMode {
+  /// Blocks are stored along a Z-order (Morton) curve, with the bits of their X and Y coordinates
+  /// interleaved, as in PlayStation Vita textures. Each mipmap level must be a power of two number
+  /// of blocks on each side. Levels that aren't square are stored as a row or column of square tiles.
+  Morton
+}
+
+/// Options controlling how the pixels of a DDS file are decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeOptions {
+  /// Multiply the color channels of each decoded pixel by its alpha
+  pub premultiply: bool,
+  /// Divide the color channels of each decoded pixel by its alpha, for files known to store
+  /// premultiplied colors, whatever their format says. Applied before `premultiply`.
+  pub unpremultiply: bool,
+  /// The channel order of the decoded images, regardless of the order used by the file.
+  /// Note that the images are still `RgbaImage`s, so `Bgra` output will have red and blue swapped.
+  pub output_order: ChannelOrder,
+  /// Reject files that deviate from the DDS specification, rather than tolerating
+  /// common mistakes made by exporters. See [`Diagnostic`] for what gets checked.
+  pub strict: bool,
+  /// The last mipmap level to decode, leaving out the smaller levels after it. Decodes every level if `None`.
+  pub max_level: Option<usize>,
+  /// Attempt to recover files with an unrecognized four-cc by decoding them as DXT1 or DXT5,
+  /// whichever one exactly accounts for the size of the payload. This is a best-effort guess.
+  pub guess_unknown: bool,
+  /// Treat the 2-bit alpha channel of formats like A2R10G10B10 as fully opaque, since it often goes
+  /// unused. Otherwise the alpha is scaled up to one of 0, 85, 170 or 255.
+  pub opaque_2bit_alpha: bool,
+  /// Decode whatever is stored in the unused X bits of formats like X8R8G8B8 and X1R5G5B5 as alpha,
+  /// for inspecting what a writer left there. Otherwise formats without an alpha mask are opaque.
+  pub preserve_x_bits: bool,
+  /// Make every decoded pixel fully opaque, whatever alpha the file stores. DXT5 and uncompressed
+  /// layers skip decoding their alpha altogether, which is faster for pipelines that never use it.
+  pub ignore_alpha: bool,
+  /// Fill out block compressed layers cut short by a truncated payload with zeros, rather than failing
+  /// with [`DecodeError::InvalidBlockLength`]. Blocks past the end of the payload decode as black.
+  pub pad_truncated: bool,
+  /// The largest percentage of a block compressed layer's blocks that may look corrupt, such as blocks
+  /// with every bit cleared or set, before failing with [`DecodeError::LikelyCorrupt`]. Textures that
+  /// really are solid black can trip this, so the check is skipped if `None`.
+  pub verify_blocks: Option<u8>,
+  /// Decode DXT5 files as DXT5nm (BC3n) normal maps, which store X in alpha and Y in green.
+  /// The decoded images hold X, Y and the rebuilt Z in red, green and blue, with opaque alpha.
+  /// Files with the `RXGB` four-cc are always decoded this way.
+  pub dxt5nm: bool,
+  /// Where each channel of the decoded pixels is taken from, for formats that store channels
+  /// somewhere unusual, like normal maps with X in alpha. Applied before any other post-processing.
+  pub swizzle: [Channel; 4],
+  /// Undo the tiling of textures exported for consoles, whose blocks are stored out of scanline order.
+  /// See [`TileMode`] for the supported modes. Only block compressed formats can be tiled.
+  pub detile: Option<TileMode>,
+  /// Make every pixel whose color exactly matches this RGB key fully transparent,
+  /// for legacy textures that use a color key such as magenta instead of an alpha channel
+  pub color_key: Option<[u8; 3]>
+}
+
+/// The order a mipmap chain is given in, see [`EncodeOptions::mip_order`]. DDS files always store
+/// the main image first, so this only changes how the chain is handed over, never the file itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MipOrder {
+  /// The main image first, down to the smallest level, as stored in the file
+  #[default]
+  LargestFirst,
+  /// The smallest level first, up to the main image, as some graphics APIs expect
+  SmallestFirst
+}
+
+/// How the texels of a partial block are filled in when an image's dimensions are not
+/// a multiple of 4. The filler texels are never decoded, but they still influence the
+/// colors chosen for the rest of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EdgePad {
+  /// Repeat the last row or column of the image, which usually gives the best edges
+  #[default]
+  Clamp,
+  /// Reflect the image back across its last row or column
+  Mirror,
+  /// Fill with transparent black
+  Zero
+}
+
+/// Options controlling how images are encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+  /// How partial blocks at the right and bottom edges are filled before being compressed
+  pub edge_pad: EdgePad,
+  /// Apply ordered dithering when reducing channels to fewer than 8 bits, as with `R5G6B5`,
+  /// trading the banding of smooth gradients for a fine noise pattern
+  pub dither: bool,
+  /// The lowest alpha that stays opaque when encoding DXT1 with punch-through alpha,
+  /// anything below it becomes fully transparent. Defaults to 128.
+  pub alpha_threshold: u8,
+  /// Let [`Dds::encode_with_mipmaps`] take only the first few levels of the mipmap chain,
+  /// rather than requiring it to go all the way down to 1x1
+  pub allow_partial: bool,
+  /// The order of the levels given to [`Dds::encode_with_mipmaps`]. They are written largest first either way.
+  pub mip_order: MipOrder
+}
+
+impl Default for DecodeOptions {
+  fn default() -> DecodeOptions {
+    DecodeOptions {
+      premultiply: false,
+      unpremultiply: false,
+      output_order: ChannelOrder::default(),
+      strict: false,
+      max_level: None,
+      guess_unknown: false,
+      opaque_2bit_alpha: false,
+      preserve_x_bits: false,
+      ignore_alpha: false,
+      pad_truncated: false,
+      verify_blocks: None,
+      dxt5nm: false,
+      swizzle: Channel::IDENTITY,
+      detile: None,
+      color_key: None
+    }
+  }
+}
+
+impl DecodeOptions {
+  /// Returns the default options, to be adjusted with the chainable setters below.
+  ///
+  /// ```rust
+  /// use dds::{ChannelOrder, DecodeOptions, Dds};
+  ///
+  /// let options = DecodeOptions::new()
+  ///   .strict(true)
+  ///   .max_level(1)
+  ///   .premultiply(true)
+  ///   .output_order(ChannelOrder::Bgra);
+  ///
+  /// let file = std::fs::File::open("./samples/dxt5.dds").unwrap();
+  /// let dds = Dds::decode_with(file, &options).unwrap();
+  /// assert_eq!(dds.layers.len(), 2);
+  /// ```
+  pub fn new() -> DecodeOptions {
+    DecodeOptions::default()
+  }
+
+  /// Sets [`DecodeOptions::premultiply`].
+  pub fn premultiply(mut self, premultiply: bool) -> DecodeOptions {
+    self.premultiply = premultiply;
+    self
+  }
+
+  /// Sets [`DecodeOptions::unpremultiply`].
+  pub fn unpremultiply(mut self, unpremultiply: bool) -> DecodeOptions {
+    self.unpremultiply = unpremultiply;
+    self
+  }
+
+  /// Sets [`DecodeOptions::output_order`].
+  pub fn output_order(mut self, output_order: ChannelOrder) -> DecodeOptions {
+    self.output_order = output_order;
+    self
+  }
+
+  /// Sets [`DecodeOptions::strict`].
+  pub fn strict(mut self, strict: bool) -> DecodeOptions {
+    self.strict = strict;
+    self
+  }
+
+  /// Only decodes the mipmap levels up to `level`, see [`DecodeOptions::max_level`].
+  pub fn max_level(mut self, level: usize) -> DecodeOptions {
+    self.max_level = Some(level);
+    self
+  }
+
+  /// Sets [`DecodeOptions::guess_unknown`].
+  pub fn guess_unknown(mut self, guess_unknown: bool) -> DecodeOptions {
+    self.guess_unknown = guess_unknown;
+    self
+  }
+
+  /// Sets [`DecodeOptions::opaque_2bit_alpha`].
+  pub fn opaque_2bit_alpha(mut self, opaque_2bit_alpha: bool) -> DecodeOptions {
+    self.opaque_2bit_alpha = opaque_2bit_alpha;
+    self
+  }
+
+  /// Sets [`DecodeOptions::preserve_x_bits`].
+  pub fn preserve_x_bits(mut self, preserve_x_bits: bool) -> DecodeOptions {
+    self.preserve_x_bits = preserve_x_bits;
+    self
+  }
+
+  /// Sets [`DecodeOptions::ignore_alpha`].
+  pub fn ignore_alpha(mut self, ignore_alpha: bool) -> DecodeOptions {
+    self.ignore_alpha = ignore_alpha;
+    self
+  }
+
+  /// Sets [`DecodeOptions::pad_truncated`].
+  pub fn pad_truncated(mut self, pad_truncated: bool) -> DecodeOptions {
+    self.pad_truncated = pad_truncated;
+    self
+  }
+
+  /// Checks block compressed layers for corruption, see [`DecodeOptions::verify_blocks`].
+  pub fn verify_blocks(mut self, threshold: u8) -> DecodeOptions {
+    self.verify_blocks = Some(threshold);
+    self
+  }
+
+  /// Sets [`DecodeOptions::dxt5nm`].
+  pub fn dxt5nm(mut self, dxt5nm: bool) -> DecodeOptions {
+    self.dxt5nm = dxt5nm;
+    self
+  }
+
+  /// Sets [`DecodeOptions::swizzle`].
+  pub fn swizzle(mut self, swizzle: [Channel; 4]) -> DecodeOptions {
+    self.swizzle = swizzle;
+    self
+  }
+
+  /// Undoes the tiling of console textures, see [`DecodeOptions::detile`].
+  pub fn detile(mut self, mode: TileMode) -> DecodeOptions {
+    self.detile = Some(mode);
+    self
+  }
+
+  /// Makes pixels of the given color transparent, see [`DecodeOptions::color_key`].
+  pub fn color_key(mut self, key: [u8; 3]) -> DecodeOptions {
+    self.color_key = Some(key);
+    self
+  }
+}
+
+impl Default for EncodeOptions {
+  fn default() -> EncodeOptions {
+    EncodeOptions {
+      edge_pad: EdgePad::default(),
+      dither: false,
+      alpha_threshold: 128,
+      allow_partial: false,
+      mip_order: MipOrder::default()
+    }
+  }
+}
+
+// Keeps track of how many bytes have been read from the inner reader
+struct CountingReader<R> {
+  reader: R,
+  count: usize
+}
+
+impl<R: Read> Read for CountingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let count = self.reader.read(buf)?;
+    self.count += count;
+    Ok(count)
+  }
+}
+
+/// The structure of a DDS file, as returned by [`Dds::describe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DdsLayout {
+  /// The number of bytes before the payload, see [`Header::size`]
+  pub header_size: usize,
+  /// Whether the file has a DX10 extended header
+  pub has_dx10: bool,
+  /// The number of mipmap levels in each texture
+  pub mipmap_count: u32,
+  /// The number of cubemap faces stored in the file, 1 for anything but cubemaps
+  pub face_count: u32,
+  /// The number of textures in a DX10 texture array, 1 for anything else
+  pub array_size: u32,
+  /// The number of slices in the main image of a volume texture, 1 for anything else
+  pub depth: u32,
+  /// Every surface in the payload, in file order
+  pub surfaces: Vec<SurfaceLayout>
+}
+
+/// The location of a single surface within the payload of a DDS file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SurfaceLayout {
+  /// The index of the texture this surface belongs to, counting each cubemap face of each array
+  /// element in turn. Always 0 for volume textures.
+  pub texture: u32,
+  /// The mipmap level of this surface
+  pub level: u32,
+  /// The slice of a volume texture's mipmap level, 0 for anything else
+  pub slice: u32,
+  pub width: u32,
+  pub height: u32,
+  /// The offset of this surface in bytes, relative to the start of the payload
+  pub offset: usize,
+  /// The size of this surface in bytes
+  pub size: usize
+}
+
+/// Represents a parsed DDS file. When serialized, the RGBA pixels of each layer are stored as base64
+/// alongside its dimensions. Serialize the `header` on its own to leave the pixels out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dds {
+  /// The parsed DDS header
+  pub header: Header,
+  /// Mipmap layers. Cubemaps and texture arrays contain the mipmap chain of each face or slice in turn.
+  /// Volume textures only keep the front slice of each level, see [`Volume`] for the rest.
+  #[serde(with = "crate::serialize::layers")]
+  pub layers: Vec<RgbaImage>
+}
+
+impl Dds {
+  /// Decodes a buffer into a header and a series of mipmap images.
+  /// Handles uncompressed and DXT1-5 compressed images, including those described by a DX10 header.
+  pub fn decode<R: Read>(reader: R) -> Result<Dds, DecodeError> {
+    Dds::decode_with(reader, &DecodeOptions::default())
+  }
+
+  /// Decodes a buffer into a header and a series of mipmap images, using the given options.
+  pub fn decode_with<R: Read>(reader: R, options: &DecodeOptions) -> Result<Dds, DecodeError> {
+    Ok(Dds::decode_diagnosed(reader, options)?.0)
+  }
+
+  /// Decodes a buffer like [`Dds::decode`], also returning every deviation from the DDS
+  /// specification that was tolerated along the way, in the order they were found.
+  pub fn decode_verbose<R: Read>(reader: R) -> Result<(Dds, Vec<Diagnostic>), DecodeError> {
+    Dds::decode_diagnosed(reader, &DecodeOptions::default())
+  }
+
+  // Decodes a buffer, collecting the diagnostics of the header and then those of the payload
+  fn decode_diagnosed<R: Read>(mut reader: R, options: &DecodeOptions) -> Result<(Dds, Vec<Diagnostic>), DecodeError> {
+    let mut counter = CountingReader { reader: &mut reader, count: 0 };
+    let raw_header = RawHeader::decode(&mut counter)?;
+    let (header, mut diagnostics) = Header::from_raw_header(raw_header, &mut counter, options)?;
+
+    // If the header parsing and the header's own idea of its size ever disagree,
+    // the payload would be misaligned and decode into garbage, so stop here
+    if counter.count != header.size() {
+      return Err(DecodeError::HeaderSizeMismatch { expected: header.size(), consumed: counter.count });
+    };
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    let layout = Layout::from_header(&header);
+    if matches!(layout, Ok(Layout::Dxt1 { alpha: false })) && !options.ignore_alpha && uses_punch_through_alpha(&buf) {
+      diagnostics.push(Diagnostic::OpaquePunchThroughAlpha);
+    };
+
+    let layers = decode_layers(&header, &buf, options)?;
+    Ok((Dds { header, layers }, diagnostics))
+  }
+
+  /// Decodes a single mipmap level of a single channel format directly into a grayscale image.
+  /// Supports A8, L8 and other luminance formats without alpha, and BC4, along with their DX10
+  /// equivalents. Fails with [`DecodeError::NotSingleChannel`] for any other format.
+  pub fn decode_gray<R: Read>(mut reader: R, level: usize) -> Result<GrayImage, DecodeError> {
+    let header = Header::decode(&mut reader)?;
+    // Formats the decoder doesn't support at all are reported as such, rather than as having several channels
+    Layout::from_header(&header)?;
+    let channel = header.gray_channel().ok_or(DecodeError::NotSingleChannel)?;
+
+    let count = header.mipmap_levels() as usize;
+    if level >= count {
+      return Err(DecodeError::MipmapLevelOutOfRange { level, count });
+    };
+
+    let options = DecodeOptions { max_level: Some(level), ..Default::default() };
+    let mut layers = Dds::decode_payload(header, reader, &options)?.layers;
+    let layer = layers.swap_remove(level);
+    Ok(map_pixels(&layer, |pixel| Luma([pixel[channel]])))
+  }
+
+  /// Decodes a buffer into a series of mipmap images, choosing the most natural color type for the format:
+  /// `Luma8` for single channel formats such as L8 and A8, `LumaA8` for luminance formats with alpha,
+  /// `Rgb8` for formats without alpha, such as X8R8G8B8, and `Rgba8` for everything else.
+  pub fn decode_dynamic<R: Read>(reader: R) -> Result<Vec<DynamicImage>, DecodeError> {
+    let dds = Dds::decode(reader)?;
+    let header = &dds.header;
+    let layers = dds.layers.iter();
+
+    let images = match (header.gray_channel(), header.pixel_format.is_luminance(), header.has_alpha()) {
+      (Some(channel), _, _) => layers
+        .map(|layer| DynamicImage::ImageLuma8(map_pixels(layer, |pixel| Luma([pixel[channel]]))))
+        .collect(),
+      (None, true, true) => layers
+        .map(|layer| DynamicImage::ImageLumaA8(map_pixels(layer, |pixel| LumaA([pixel[0], pixel[3]]))))
+        .collect(),
+      (None, _, false) => layers
+        .map(|layer| DynamicImage::ImageRgb8(map_pixels(layer, |pixel| Rgb([pixel[0], pixel[1], pixel[2]]))))
+        .collect(),
+      (None, _, true) => dds.layers.into_iter().map(DynamicImage::ImageRgba8).collect()
+    };
+
+    Ok(images)
+  }
+
+  /// Splits the payload of an A8R8G8B8 file into the bytes of each layer, without copying or
+  /// converting anything, given the header and the payload that follows it. Each pixel is stored in
+  /// B, G, R, A byte order. Rows padded out to a larger pitch can't be borrowed as a single slice, so
+  /// other pixel formats and padded rows fail with [`DecodeError::UnsupportedPixelFormat`].
+  pub fn decode_uncompressed_borrowed<'a>(header: &Header, data: &'a [u8]) -> Result<Vec<&'a [u8]>, DecodeError> {
+    let tightly_packed = header.pitch.is_none_or(|pitch| pitch == header.width as usize * 4);
+    if header.compression != Compression::None || header.pixel_format != PixelFormat::A8R8G8B8 || !tightly_packed {
+      return Err(DecodeError::UnsupportedPixelFormat(header.pixel_format));
+    };
+
+    header.layer_offsets()?.into_iter()
+      .map(|(offset, size)| match data.get(offset..offset + size) {
+        Some(layer) => Ok(layer),
+        None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+      })
+      .collect()
+  }
+
+  /// Decodes only the alpha of a single mipmap level of a DXT5 file into a grayscale image, such as
+  /// for an opacity mask. The color half of each block is skipped, so this is much faster than a
+  /// full decode. Fails with [`DecodeError::UnsupportedCompression`] for anything other than DXT4/5.
+  pub fn decode_dxt5_alpha<R: Read>(mut reader: R, level: usize) -> Result<GrayImage, DecodeError> {
+    let header = Header::decode(&mut reader)?;
+    if Layout::from_header(&header)? != Layout::Dxt5 {
+      return Err(DecodeError::UnsupportedCompression(header.compression));
+    };
+
+    let count = header.mipmap_levels() as usize;
+    let (offset, size) = match header.layer_offsets()?.get(level) {
+      Some(&layer) if level < count => layer,
+      _ => return Err(DecodeError::MipmapLevelOutOfRange { level, count })
+    };
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    let layer = &buf[offset.min(buf.len())..(offset + size).min(buf.len())];
+    let level_header = header.level_header(level);
+    let (width, height) = (level_header.width as usize, level_header.height as usize);
+    let layer = unpad_block_rows(&level_header, 0, layer, width, height, 16);
+    decode_layer_dxt5_alpha(&layer, level_header.width, level_header.height)
+  }
+
+  /// Decodes a small preview that fits within `max_dim` pixels on both sides. Only the smallest
+  /// mipmap level that is still at least `max_dim` pixels on its larger side gets decoded, which
+  /// is then resized down to fit. Files without mipmaps always decode the main image.
+  pub fn decode_thumbnail<R: Read>(mut reader: R, max_dim: u32) -> Result<RgbaImage, DecodeError> {
+    let header = Header::decode(&mut reader)?;
+    let max_dim = max_dim.max(1);
+
+    let level = (0..header.mipmap_levels() as usize)
+      .take_while(|&level| {
+        let level_header = header.level_header(level);
+        level_header.width.max(level_header.height) >= max_dim
+      })
+      .last()
+      .unwrap_or(0);
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    // Decode the chosen level on its own, as if it were the main image of a texture without mipmaps
+    let (offset, size) = header.layer_offsets()?[level];
+    let layer = &buf[offset.min(buf.len())..(offset + size).min(buf.len())];
+    let mut layers = decode_layers(&header.level_header(level), layer, &DecodeOptions::default())?;
+    let image = layers.swap_remove(0);
+
+    let (width, height) = image.dimensions();
+    match width.max(height) {
+      size if size <= max_dim => Ok(image),
+      size => {
+        let scale = |dim: u32| ((dim as u64 * max_dim as u64 / size as u64) as u32).max(1);
+        Ok(imageops::resize(&image, scale(width), scale(height), imageops::FilterType::Triangle))
+      }
+    }
+  }
+
+  /// Returns the average color of a texture, such as for a placeholder shown while it loads. Only the
+  /// smallest mipmap level is decoded, which is far cheaper than decoding the main image, and the rest
+  /// of the file after it isn't read at all. Files without mipmaps average the main image instead.
+  pub fn dominant_color<R: Read>(mut reader: R) -> Result<[u8; 4], DecodeError> {
+    let header = Header::decode(&mut reader)?;
+    let level = header.mipmap_levels().max(1) as usize - 1;
+
+    let (offset, size) = header.layer_offsets()?[level];
+    let mut buf = Vec::with_capacity(offset + size);
+    reader.take((offset + size) as u64).read_to_end(&mut buf)?;
+
+    let layer = &buf[offset.min(buf.len())..];
+    let image = decode_layers(&header.level_header(level), layer, &DecodeOptions::default())?.swap_remove(0);
+
+    let mut sums = [0u64; 4];
+    for pixel in image.pixels() {
+      for (sum, &channel) in sums.iter_mut().zip(pixel.0.iter()) {
+        *sum += channel as u64;
+      };
+    };
+
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    Ok(sums.map(|sum| ((sum + count / 2) / count) as u8))
+  }
+
+  /// Decodes a file that is missing its `DDS ` magic bytes, so the reader starts directly
+  /// at the 124-byte header. This is an escape hatch for recovering damaged files,
+  /// [`Dds::decode`] should be preferred whenever possible.
+  pub fn decode_headerless<R: Read>(mut reader: R) -> Result<Dds, DecodeError> {
+    let options = DecodeOptions::default();
+    let raw_header = RawHeader::decode_headerless(&mut reader)?;
+    let (header, _) = Header::from_raw_header(raw_header, &mut reader, &options)?;
+    Dds::decode_payload(header, reader, &options)
+  }
+
+  // Reads the rest of the file and decodes it as the payload described by `header`
+  fn decode_payload<R: Read>(header: Header, mut reader: R, options: &DecodeOptions) -> Result<Dds, DecodeError> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    let layers = decode_layers(&header, &buf, options)?;
+
+    Ok(Dds { header, layers })
+  }
+
+  /// Seeks to `offset` and decodes the DDS file found there, for files embedded in other containers.
+  /// Only the bytes of the texture are read, so the reader is left positioned right after its end,
+  /// and pass `&mut reader` to keep reading the rest of the container from there.
+  pub fn decode_at_offset<R: Read + Seek>(mut reader: R, offset: u64) -> Result<Dds, DecodeError> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let header = Header::decode(&mut reader)?;
+    let size = header.size() + payload_size(&header)?;
+
+    reader.seek(SeekFrom::Start(offset))?;
+    Dds::decode(reader.take(size as u64))
+  }
+
+  /// Reads the header of a buffer and describes where every surface lies in the payload,
+  /// without decoding any pixels. Fails if the format isn't supported by the decoder.
+  pub fn describe<R: Read>(reader: R) -> Result<DdsLayout, DecodeError> {
+    let header = Header::decode(reader)?;
+    let levels = header.mipmap_levels();
+
+    let mut surfaces = Vec::new();
+    let layer_sizes = header.get_layer_sizes();
+    for (i, ((height, width), (offset, size))) in layer_sizes.into_iter().zip(header.layer_offsets()?).enumerate() {
+      let (texture, level) = (i as u32 / levels, i as u32 % levels);
+      let (width, height) = (width as u32, height as u32);
+      // Every slice of a volume level is stored in turn, before the next level
+      let depth = header.layer_depth(i);
+      let size = size / depth;
+      for slice in 0..depth {
+        let offset = offset + slice * size;
+        surfaces.push(SurfaceLayout { texture, level, slice: slice as u32, width, height, offset, size });
+      };
+    };
+
+    Ok(DdsLayout {
+      header_size: header.size(),
+      has_dx10: header.dx10.is_some(),
+      mipmap_count: levels,
+      face_count: header.face_count(),
+      array_size: header.array_size(),
+      depth: header.depth,
+      surfaces
+    })
+  }
+
+  /// Decodes a tangent space normal map that only stores X and Y, such as a BC5 file, into a series
+  /// of mipmap images with Z rebuilt in blue. Each normal is kept at unit length, and alpha is opaque.
+  pub fn decode_normal_map<R: Read>(reader: R) -> Result<Vec<RgbaImage>, DecodeError> {
+    let mut layers = Dds::decode(reader)?.layers;
+    for layer in layers.iter_mut() {
+      layer.pixels_mut().for_each(|pixel| reconstruct_z(&mut pixel.0));
+    };
+
+    Ok(layers)
+  }
+
+  /// Decodes a buffer into a series of mipmap images without reducing them to 8 bits per channel,
+  /// so no precision is lost for high bit depth and floating point formats.
+  pub fn decode_native<R: Read>(mut reader: R) -> Result<NativeDds, DecodeError> {
+    let header = Header::decode(&mut reader)?;
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    decode_layers_native(&header, &buf, &DecodeOptions::default())
+  }
+
+  /// Decodes a buffer into a series of mipmap images of any [`DecodedPixel`] type. Each layer is
+  /// first decoded at its native precision, then converted, so decoding a 16-bit format into
+  /// `Rgba<u16>` or a floating point format into `Rgba<f32>` loses nothing.
+  pub fn decode_as<P: DecodedPixel, R: Read>(reader: R) -> Result<Vec<DecodedImage<P>>, DecodeError> {
+    Dds::decode_as_with(reader, &DecodeOptions::default())
+  }
+
+  /// Decodes a buffer into a series of mipmap images of any [`DecodedPixel`] type, with the given options.
+  pub fn decode_as_with<P: DecodedPixel, R: Read>(mut reader: R, options: &DecodeOptions) -> Result<Vec<DecodedImage<P>>, DecodeError> {
+    let header = Header::decode_with(&mut reader, options)?;
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    decode_layers_as(&header, &buf, options)
+  }
+
+  /// Decodes a buffer into a series of mipmap images with 16 bits per channel, such as the
+  /// A16B16G16R16 format, which is read from the numeric D3DFORMAT four-cc 36 or the DXGI format
+  /// `R16G16B16A16_UNORM`. Formats with fewer bits per channel are scaled up to the full 16-bit
+  /// range, and floating point formats are clamped to `[0, 1]` first.
+  pub fn decode_u16<R: Read>(reader: R) -> Result<Vec<Rgba16Image>, DecodeError> {
+    Dds::decode_as(reader)
+  }
+
+  /// Estimates the size of `image` once compressed, as a fraction of its uncompressed RGBA size.
+  /// No compression is actually performed. Returns `None` for compressions without a fixed size,
+  /// see [`Compression::surface_bytes`].
+  pub fn estimate_ratio(image: &RgbaImage, compression: Compression) -> Option<f64> {
+    let (width, height) = image.dimensions();
+    let uncompressed_bytes = width as usize * height as usize * 4;
+    match (compression.surface_bytes(width, height), uncompressed_bytes) {
+      (_, 0) | (None, _) => None,
+      (Some(compressed_bytes), uncompressed_bytes) => Some(compressed_bytes as f64 / uncompressed_bytes as f64)
+    }
+  }
+
+  /// Returns true if every pixel of the main image is fully opaque, regardless of whether the format
+  /// has an alpha channel. Files whose alpha goes unused can be re-encoded without it, such as DXT5 as DXT1.
+  pub fn is_opaque(&self) -> bool {
+    self.layers.first().is_none_or(is_image_opaque)
+  }
+
+  /// Lays out one mipmap level of every texture array slice in a grid with `columns` slices per row,
+  /// for previewing the whole array at once. Slices are placed left to right, then top to bottom.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `columns` is zero, or if `level` is not one of the file's mipmap levels.
+  pub fn array_to_sheet(&self, columns: usize, level: usize) -> RgbaImage {
+    assert!(columns > 0, "columns must be greater than zero");
+
+    let levels = self.header.mipmap_levels() as usize;
+    assert!(level < levels, "mipmap level {} is out of range for {} levels", level, levels);
+
+    let slices = self.layers.len() / levels;
+    let (width, height) = self.layers[level].dimensions();
+    let rows = slices.div_ceil(columns);
+    let mut sheet = RgbaImage::new(width * columns.min(slices) as u32, height * rows as u32);
+    for (i, slice) in self.layers.iter().skip(level).step_by(levels).enumerate() {
+      let (x, y) = ((i % columns) as u32 * width, (i / columns) as u32 * height);
+      imageops::replace(&mut sheet, slice, x, y);
+    };
+
+    sheet
+  }
+
+  /// Replaces a single layer, such as a regenerated mipmap. The image must have the
+  /// dimensions the header expects for that layer, leaving every other layer untouched.
+  pub fn replace_layer(&mut self, level: usize, image: RgbaImage) -> Result<(), EncodeError> {
+    let layer_sizes = self.header.get_layer_sizes();
+    let (height, width) = match layer_sizes.get(level) {
+      Some(&size) if level < self.layers.len() => size,
+      _ => return Err(EncodeError::MipmapLevelOutOfRange { level, count: self.layers.len() })
+    };
+
+    let expected = (width as u32, height as u32);
+    let found = image.dimensions();
+    if found != expected {
+      return Err(EncodeError::InvalidMipmapDimensions { level, expected, found });
+    };
+
+    self.layers[level] = image;
+    Ok(())
+  }
+
+  /// Returns the layers with each mipmap chain ordered from the smallest level up to the main image,
+  /// as some graphics APIs expect. Cubemaps and texture arrays keep their faces and slices in order,
+  /// with only the chain of each one reversed. See [`MipOrder`] for encoding chains in this order.
+  pub fn mips_smallest_first(&self) -> Vec<&RgbaImage> {
+    let surfaces = (self.header.face_count() * self.header.array_size()) as usize;
+    let levels = (self.layers.len() / surfaces.max(1)).max(1);
+    self.layers.chunks(levels)
+      .flat_map(|chain| chain.iter().rev())
+      .collect()
+  }
+
+  /// Saves a single layer as a PNG file. PNG colors are never premultiplied, so the colors of
+  /// premultiplied formats are divided by alpha first, see [`Header::is_premultiplied`]. This
+  /// assumes the layers hold the colors as stored, so layers already decoded with
+  /// [`DecodeOptions::unpremultiply`] would be divided twice.
+  pub fn save_png<P: AsRef<Path>>(&self, path: P, level: usize) -> Result<(), EncodeError> {
+    let layer = self.layers.get(level)
+      .ok_or(EncodeError::MipmapLevelOutOfRange { level, count: self.layers.len() })?;
+
+    let mut image = layer.clone();
+    if self.header.is_premultiplied() {
+      image.pixels_mut().for_each(|pixel| unpremultiply(&mut pixel.0));
+    };
+
+    image.save_with_format(path, image::ImageFormat::Png)?;
+    Ok(())
+  }
+
+  /// Re-packs every layer into a different uncompressed pixel format, returning the layers as they
+  /// decode from the new format. Channels the target format lacks are dropped, and narrower channels
+  /// are rounded to the nearest representable value. Luminance formats are unsupported targets.
+  /// Texture arrays need a DX10 header, and decoding only keeps the front slice of each level of
+  /// a volume texture, so both fail with [`EncodeError::UnsupportedTextureKind`].
+  pub fn transcode_uncompressed(&self, target: PixelFormat) -> Result<Dds, EncodeError> {
+    match self.header.texture_kind() {
+      kind @ (TextureKind::Array | TextureKind::Volume) => return Err(EncodeError::UnsupportedTextureKind(kind)),
+      TextureKind::Texture2D | TextureKind::Cubemap => ()
+    };
+
+    let (bits_per_pixel, channel_masks) = match target.masks() {
+      Some(masks) if !target.is_luminance() => masks,
+      _ => return Err(EncodeError::UnsupportedPixelFormat(target))
+    };
+
+    let header = Header {
+      compression: Compression::None,
+      fourcc: [0; 4],
+      pixel_format: target,
+      pixel_bytes: bits_per_pixel as usize / 8,
+      bits_per_pixel,
+      channel_masks,
+      pitch: Some(row_pitch(self.header.width, bits_per_pixel)),
+      dx10: None,
+      ..self.header.clone()
+    };
+
+    let buf = encode_layers_uncompressed(&header, &self.layers, false);
+    let layers = decode_layers(&header, &buf, &DecodeOptions::default())?;
+    Ok(Dds { header, layers })
+  }
+
+  /// Encodes an RGBA image as an uncompressed A8R8G8B8 DDS.
+  pub fn encode_uncompressed<W: Write>(mut writer: W, image: &RgbaImage) -> Result<(), EncodeError> {
+    let (width, height) = image.dimensions();
+    RawHeader::new_uncompressed(height, width).encode(&mut writer)?;
+
+    let data: &[u8] = image.as_raw();
+    writer.write_all(data)?;
+
+    Ok(())
+  }
+
+  /// Encodes an RGBA image in any of the uncompressed, non-luminance pixel formats. Channels the
+  /// format lacks are dropped, and narrower channels are rounded, or dithered if the options ask for it.
+  pub fn encode_format<W: Write>(mut writer: W, image: &RgbaImage, pixel_format: PixelFormat, options: &EncodeOptions) -> Result<(), EncodeError> {
+    let (bits_per_pixel, channel_masks) = match pixel_format.masks() {
+      Some(masks) if !pixel_format.is_luminance() => masks,
+      _ => return Err(EncodeError::UnsupportedPixelFormat(pixel_format))
+    };
+
+    let (width, height) = image.dimensions();
+    let header = Header {
+      height,
+      width,
+      depth: 1,
+      mipmap_count: 0,
+      compression: Compression::None,
+      fourcc: [0; 4],
+      pixel_format,
+      pixel_bytes: bits_per_pixel as usize / 8,
+      bits_per_pixel,
+      channel_masks,
+      pitch: Some(row_pitch(width, bits_per_pixel)),
+      dx10: None,
+      color_space: ColorSpace::Unknown,
+      caps: DDSCAPS_TEXTURE,
+      caps2: 0,
+      reserved: [0; 11],
+      reserved2: 0
+    };
+
+    header.to_raw_header().encode(&mut writer)?;
+    writer.write_all(&encode_layers_uncompressed(&header, std::slice::from_ref(image), options.dither))?;
+
+    Ok(())
+  }
+
+  /// Encodes an RGBA image as an uncompressed 24-bit R8G8B8 DDS, dropping the alpha channel.
+  /// Takes a quarter less space than [`Dds::encode_uncompressed`] for opaque images.
+  pub fn encode_rgb<W: Write>(writer: W, image: &RgbaImage) -> Result<(), EncodeError> {
+    Dds::encode_format(writer, image, PixelFormat::R8G8B8, &EncodeOptions::default())
+  }
+
+  /// Encodes a grayscale image as an uncompressed L8 DDS.
+  pub fn encode_l8<W: Write>(mut writer: W, image: &GrayImage) -> Result<(), EncodeError> {
+    let (width, height) = image.dimensions();
+    RawHeader::new_luminance(height, width).encode(&mut writer)?;
+
+    let data: &[u8] = image.as_raw();
+    writer.write_all(data)?;
+
+    Ok(())
+  }
+
+  /// Encodes the mipmap chains of six cubemap faces, ordered as in [`CubeFace::ALL`].
+  /// Every face must have the same dimensions and number of mipmaps, and each mipmap
+  /// must be half the size of the one before it. Supports the same compressions as [`Dds::encode`].
+  pub fn encode_cubemap<W: Write>(mut writer: W, faces: &[Vec<RgbaImage>; 6], compression: Compression) -> Result<(), EncodeError> {
+    let layout = encoder_layout(compression)?;
+    let (width, height) = match faces[0].first() {
+      Some(image) => image.dimensions(),
+      None => return Err(EncodeError::MismatchedCubemapFaces)
+    };
+
+    let mipmap_count = faces[0].len();
+    for face in faces.iter() {
+      if face.len() != mipmap_count {
+        return Err(EncodeError::MismatchedCubemapFaces);
+      };
+
+      match check_mipmap_chain(face, width, height) {
+        Err(EncodeError::InvalidMipmapDimensions { level: 0, .. }) => return Err(EncodeError::MismatchedCubemapFaces),
+        result => result?
+      };
+    };
+
+    let mut raw_header = RawHeader::new_encoded(height, width, compression);
+    raw_header.caps |= DDSCAPS_COMPLEX;
+    raw_header.caps2 = DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES;
+    raw_header.mipmap_count = mipmap_count as u32;
+    if mipmap_count > 1 {
+      raw_header.flags |= DDSD_MIPMAPCOUNT;
+      raw_header.caps |= DDSCAPS_MIPMAP;
+    };
+
+    raw_header.encode(&mut writer)?;
+    write_layers(writer, faces.iter().flatten(), layout, &EncodeOptions::default())
+  }
+
+  /// Writes the file back out with a header that follows the DDS specification, with the flags, caps,
+  /// pitch and pixel format size all derived from the image itself rather than copied from the original.
+  /// DXT1, DXT3, DXT5 and uncompressed formats with channel masks are kept, although DXT layers are
+  /// recompressed and channels wider than 8 bits lose their extra precision. Anything else is written
+  /// as A8R8G8B8. Texture arrays need a DX10 header, so they are unsupported.
+  pub fn encode_repaired<W: Write>(&self, mut writer: W) -> Result<(), EncodeError> {
+    if self.header.array_size() > 1 {
+      return Err(EncodeError::UnsupportedCompression(Compression::DX10));
+    };
+
+    let compression = match self.header.compression {
+      compression @ (Compression::DXT1 | Compression::DXT3 | Compression::DXT5) => compression,
+      _ => Compression::None
+    };
+
+    let pixel_format = match Layout::from_header(&self.header) {
+      Ok(Layout::Uncompressed) if self.header.pixel_format != PixelFormat::Unknown => self.header.pixel_format,
+      _ => PixelFormat::A8R8G8B8
+    };
+
+    let (bits_per_pixel, channel_masks) = match compression {
+      Compression::None => pixel_format.masks().expect("pixel format has no masks"),
+      _ => (0, [0; 4])
+    };
+
+    let header = Header {
+      mipmap_count: (self.layers.len() / self.header.face_count() as usize).max(1) as u32,
+      compression,
+      fourcc: compression.to_bytes(),
+      pixel_format: match compression {
+        Compression::None => pixel_format,
+        _ => PixelFormat::Unknown
+      },
+      pixel_bytes: bits_per_pixel as usize / 8,
+      bits_per_pixel,
+      channel_masks,
+      pitch: match compression {
+        Compression::None => Some(row_pitch(self.header.width, bits_per_pixel)),
+        _ => None
+      },
+      dx10: None,
+      caps: 0,
+      caps2: match self.header.is_cubemap() {
+        true => self.header.caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES),
+        false => 0
+      },
+      ..self.header.clone()
+    };
+
+    // Compressed formats give the size of the main image instead of a pitch
+    let mut raw_header = header.to_raw_header();
+    if let Some(linear_size) = compression.surface_bytes(header.width, header.height) {
+      raw_header.flags |= DDSD_LINEARSIZE;
+      raw_header.pitch_or_linear_size = linear_size as u32;
+    };
+
+    raw_header.encode(&mut writer)?;
+    match Layout::from_header(&header) {
+      Ok(Layout::Uncompressed) => writer.write_all(&encode_layers_uncompressed(&header, &self.layers, false))?,
+      Ok(layout) => for layer in self.layers.iter() {
+        writer.write_all(&encode_image_dxt(layer, layout, &EncodeOptions::default()))?;
+      },
+      Err(_) => unreachable!("repaired headers always have a supported layout")
+    };
+
+    Ok(())
+  }
+
+  /// Encodes a series of Pixels as a bunch of bytes, suitable for writing to disk, etc.
+  /// Supports uncompressed RGBA images, and DXT1, DXT3 and DXT5 compression. DXT1A
+  /// is DXT1 with punch-through alpha, see [`EncodeOptions::alpha_threshold`].
+  pub fn encode<W: Write>(writer: W, image: &RgbaImage, compression: Compression) -> Result<(), EncodeError> {
+    Dds::encode_with(writer, image, compression, &EncodeOptions::default())
+  }
+
+  /// Encodes an image with whichever compression suits it, DXT1 if every pixel is fully
+  /// opaque and DXT5 otherwise. Returns the compression that was picked.
+  pub fn encode_auto<W: Write>(writer: W, image: &RgbaImage) -> Result<Compression, EncodeError> {
+    let compression = match is_image_opaque(image) {
+      true => Compression::DXT1,
+      false => Compression::DXT5
+    };
+
+    Dds::encode(writer, image, compression)?;
+    Ok(compression)
+  }
+
+  /// Encodes an image like [`Dds::encode`], using the given options.
+  pub fn encode_with<W: Write>(writer: W, image: &RgbaImage, compression: Compression, options: &EncodeOptions) -> Result<(), EncodeError> {
+    Dds::encode_levels(writer, &[image], compression, options)
+  }
+
+  /// Encodes a full mipmap chain, starting with the main image and going all the way down to 1x1,
+  /// or the other way around if [`EncodeOptions::mip_order`] says so. Each level must be half the size
+  /// of the one before it in the file, rounded down. The chain may stop early if [`EncodeOptions::allow_partial`]
+  /// is set. Supports the same compressions as [`Dds::encode`].
+  pub fn encode_with_mipmaps<W: Write>(writer: W, levels: &[RgbaImage], compression: Compression, options: &EncodeOptions) -> Result<(), EncodeError> {
+    let levels: Vec<&RgbaImage> = match options.mip_order {
+      MipOrder::LargestFirst => levels.iter().collect(),
+      MipOrder::SmallestFirst => levels.iter().rev().collect()
+    };
+
+    let (width, height) = levels.first().map_or((0, 0), |image| image.dimensions());
+    let expected = full_mipmap_count(width, height) as usize;
+    let partial = options.allow_partial && (1..expected).contains(&levels.len());
+    if levels.len() != expected && !partial {
+      return Err(EncodeError::InvalidMipmapCount { expected, found: levels.len() });
+    };
+
+    check_mipmap_chain(levels.iter().copied(), width, height)?;
+    Dds::encode_levels(writer, &levels, compression, options)
+  }
+
+  // Writes a header describing the mipmap chain, followed by each level in turn
+  fn encode_levels<W: Write>(mut writer: W, levels: &[&RgbaImage], compression: Compression, options: &EncodeOptions) -> Result<(), EncodeError> {
+    let layout = encoder_layout(compression)?;
+    let (width, height) = levels.first().map_or((0, 0), |image| image.dimensions());
+    let mut raw_header = RawHeader::new_encoded(height, width, compression);
+    if levels.len() > 1 {
+      raw_header.flags |= DDSD_MIPMAPCOUNT;
+      raw_header.mipmap_count = levels.len() as u32;
+      raw_header.caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    };
+
+    raw_header.encode(&mut writer)?;
+    write_layers(writer, levels.iter().copied(), layout, options)
+  }
+}
+
+// Returns the layout the encoder writes for a compression, failing for anything it can't write
+fn encoder_layout(compression: Compression) -> Result<Layout, EncodeError> {
+  match compression {
+    Compression::None => Ok(Layout::Uncompressed),
+    Compression::DXT1 => Ok(Layout::Dxt1 { alpha: false }),
+    Compression::DXT1A => Ok(Layout::Dxt1 { alpha: true }),
+    Compression::DXT3 => Ok(Layout::Dxt3),
+    Compression::DXT5 => Ok(Layout::Dxt5),
+    compression => Err(EncodeError::UnsupportedCompression(compression))
+  }
+}
+
+// Writes each layer in turn, either as RGBA pixels or compressed into blocks
+fn write_layers<'a, W: Write, I: IntoIterator<Item = &'a RgbaImage>>(mut writer: W, layers: I, layout: Layout, options: &EncodeOptions) -> Result<(), EncodeError> {
+  for image in layers {
+    match layout {
+      Layout::Uncompressed => writer.write_all(image.as_raw())?,
+      layout => writer.write_all(&encode_image_dxt(image, layout, options))?
+    };
+  };
+
+  Ok(())
+}
+
+// Checks that each level of a mipmap chain is half the size of the one before it, starting from `width` by `height`
+fn check_mipmap_chain<'a, I: IntoIterator<Item = &'a RgbaImage>>(levels: I, width: u32, height: u32) -> Result<(), EncodeError> {
+  for (level, image) in levels.into_iter().enumerate() {
+    let expected = ((width >> level).max(1), (height >> level).max(1));
+    let found = image.dimensions();
+    if found != expected {
+      return Err(EncodeError::InvalidMipmapDimensions { level, expected, found });
+    };
+  };
+
+  Ok(())
+}
+
+// Returns true if no pixel of the image has any transparency
+fn is_image_opaque(image: &RgbaImage) -> bool {
+  image.pixels().all(|pixel| pixel.0[3] == 255)
+}
+
+// Converts each pixel of an RGBA image into another pixel type, usually with fewer channels
+fn map_pixels<P, F>(image: &RgbaImage, convert: F) -> ImageBuffer<P, Vec<u8>>
+where P: Pixel<Subpixel = u8> + 'static, F: Fn([u8; 4]) -> P {
+  let (width, height) = image.dimensions();
+  ImageBuffer::from_fn(width, height, |x, y| convert(image.get_pixel(x, y).0))
+}
+
+/// Returns true if `bytes` starts with the `DDS ` magic bytes, for quickly filtering
+/// files before decoding them. Nothing past the magic bytes is checked.
+pub fn is_dds(bytes: &[u8]) -> bool {
+  bytes.starts_with(b"DDS ")
+}
+
+/// Encodes an image into a new in-memory DDS file, see [`Dds::encode`].
+pub fn encode_to_vec(image: &RgbaImage, compression: Compression) -> Result<Vec<u8>, EncodeError> {
+  let mut bytes = Vec::new();
+  Dds::encode(&mut bytes, image, compression)?;
+  Ok(bytes)
+}
+
+/// Decodes several DDS files in parallel, returning the results in the same order as `paths`.
+#[cfg(feature = "rayon")]
+pub fn decode_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<Dds, DecodeError>> {
+  paths.par_iter()
+    .map(|path| {
+      let file = File::open(path)?;
+      Dds::decode(BufReader::new(file))
+    })
+    .collect()
+}
+
+/// Incrementally decodes a DDS file from chunks of bytes as they arrive, for example from
+/// a network stream, without needing a blocking reader.
+///
+/// ```rust
+/// use dds::DdsStreamDecoder;
+///
+/// let bytes = std::fs::read("./samples/dxt1.dds").unwrap();
+///
+/// let mut decoder = DdsStreamDecoder::new();
+/// for chunk in bytes.chunks(1024) {
+///   decoder.feed(chunk);
+///   if let Some(result) = decoder.try_finish() {
+///     println!("{:?}", result.unwrap().header);
+///     break;
+///   };
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DdsStreamDecoder {
+  buf: Vec<u8>
+}
+
+impl DdsStreamDecoder {
+  pub fn new() -> DdsStreamDecoder {
+    DdsStreamDecoder::default()
+  }
+
+  /// Appends the next chunk of the file.
+  pub fn feed(&mut self, bytes: &[u8]) {
+    self.buf.extend_from_slice(bytes);
+  }
+
+  /// The number of bytes fed to the decoder so far.
+  pub fn len(&self) -> usize {
+    self.buf.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.buf.is_empty()
+  }
+
+  /// Attempts to decode the bytes fed so far. Returns `None` if more data is needed,
+  /// otherwise returns the result of decoding the file.
+  pub fn try_finish(&self) -> Option<Result<Dds, DecodeError>> {
+    let header = match Header::decode(self.buf.as_slice()) {
+      Ok(header) => header,
+      Err(DecodeError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+      Err(err) => return Some(Err(err))
+    };
+
+    let size = match payload_size(&header) {
+      Ok(size) => header.size() + size,
+      Err(err) => return Some(Err(err))
+    };
+
+    if self.buf.len() < size {
+      return None;
+    };
+
+    Some(Dds::decode(self.buf.as_slice()))
   }
 }
@@ -0,0 +1,176 @@
+use image::RgbaImage;
+
+use crate::{Dds, Header};
+
+/// Computes the peak signal-to-noise ratio between two images in decibels, over all four
+/// channels. Identical images give `f64::INFINITY`, and higher values mean closer images.
+///
+/// # Panics
+///
+/// Panics if the images have different dimensions.
+pub fn image_psnr(a: &RgbaImage, b: &RgbaImage) -> f64 {
+  assert_eq!(a.dimensions(), b.dimensions(), "images must have the same dimensions");
+
+  let squared_error: f64 = a.as_raw().iter().zip(b.as_raw().iter())
+    .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+    .sum();
+  let mse = squared_error / a.as_raw().len().max(1) as f64;
+
+  if mse == 0.0 {
+    return f64::INFINITY;
+  };
+
+  10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+// Converts a pixel to its BT.601 luma value
+fn luma(pixel: &[u8]) -> f64 {
+  0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+}
+
+/// Computes the mean structural similarity between two images, using the luma of each pixel
+/// over 8x8 windows. Identical images give `1.0`, and lower values mean less similar images.
+///
+/// # Panics
+///
+/// Panics if the images have different dimensions.
+pub fn image_ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+  assert_eq!(a.dimensions(), b.dimensions(), "images must have the same dimensions");
+
+  const WINDOW: u32 = 8;
+  const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+  const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+  let (width, height) = a.dimensions();
+  let mut total = 0.0;
+  let mut windows = 0;
+  for wy in (0..height).step_by(WINDOW as usize) {
+    for wx in (0..width).step_by(WINDOW as usize) {
+      let pixels = (wy..(wy + WINDOW).min(height))
+        .flat_map(|y| (wx..(wx + WINDOW).min(width)).map(move |x| (x, y)))
+        .map(|(x, y)| (luma(&a.get_pixel(x, y).0), luma(&b.get_pixel(x, y).0)))
+        .collect::<Vec<_>>();
+
+      let n = pixels.len() as f64;
+      let mean_a = pixels.iter().map(|p| p.0).sum::<f64>() / n;
+      let mean_b = pixels.iter().map(|p| p.1).sum::<f64>() / n;
+      let var_a = pixels.iter().map(|p| (p.0 - mean_a).powi(2)).sum::<f64>() / n;
+      let var_b = pixels.iter().map(|p| (p.1 - mean_b).powi(2)).sum::<f64>() / n;
+      let covariance = pixels.iter().map(|p| (p.0 - mean_a) * (p.1 - mean_b)).sum::<f64>() / n;
+
+      total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2)) /
+        ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+      windows += 1;
+    };
+  };
+
+  match windows {
+    0 => 1.0,
+    windows => total / windows as f64
+  }
+}
+
+/// How a single pair of layers differs, see [`Dds::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerDiff {
+  /// The layers have different dimensions, so their pixels can't be compared
+  DimensionMismatch { level: usize, left: (u32, u32), right: (u32, u32) },
+  /// The layers have the same dimensions. Errors are the absolute differences between channels.
+  Compared {
+    level: usize,
+    /// The largest error of any channel of any pixel
+    max_error: u8,
+    /// The mean error over all four channels of every pixel
+    mean_error: f64,
+    /// The number of pixels with an error in any channel
+    differing_pixels: usize,
+    /// The peak signal-to-noise ratio between the layers, see [`image_psnr`]
+    psnr: f64
+  }
+}
+
+impl LayerDiff {
+  /// Returns true if the layers are exactly the same.
+  pub fn is_identical(&self) -> bool {
+    matches!(self, LayerDiff::Compared { max_error: 0, .. })
+  }
+}
+
+/// A structural comparison of two DDS files, see [`Dds::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DdsDiff {
+  /// The names of the header fields that differ between the files
+  pub header_fields: Vec<&'static str>,
+  /// The number of layers in each file
+  pub layer_counts: (usize, usize),
+  /// A comparison of every layer both files have
+  pub layers: Vec<LayerDiff>
+}
+
+impl DdsDiff {
+  /// Returns true if the files have the same header fields and exactly the same layers.
+  pub fn is_identical(&self) -> bool {
+    self.header_fields.is_empty() && self.layer_counts.0 == self.layer_counts.1 &&
+      self.layers.iter().all(LayerDiff::is_identical)
+  }
+}
+
+// Lists the header fields whose values differ, leaving out fields derived from others
+fn diff_headers(left: &Header, right: &Header) -> Vec<&'static str> {
+  let fields = [
+    ("width", left.width != right.width),
+    ("height", left.height != right.height),
+    ("depth", left.depth != right.depth),
+    ("mipmap_count", left.mipmap_count != right.mipmap_count),
+    ("compression", left.compression != right.compression),
+    ("pixel_format", left.pixel_format != right.pixel_format),
+    ("channel_masks", left.channel_masks != right.channel_masks),
+    ("pitch", left.pitch != right.pitch),
+    ("dx10", left.dx10 != right.dx10),
+    ("color_space", left.color_space != right.color_space),
+    ("caps", left.caps != right.caps),
+    ("caps2", left.caps2 != right.caps2),
+    ("reserved", left.reserved != right.reserved || left.reserved2 != right.reserved2)
+  ];
+
+  fields.iter().filter(|(_, differs)| *differs).map(|(name, _)| *name).collect()
+}
+
+fn diff_layer(level: usize, left: &RgbaImage, right: &RgbaImage) -> LayerDiff {
+  if left.dimensions() != right.dimensions() {
+    return LayerDiff::DimensionMismatch { level, left: left.dimensions(), right: right.dimensions() };
+  };
+
+  let mut max_error = 0;
+  let mut total_error = 0u64;
+  let mut differing_pixels = 0;
+  for (a, b) in left.pixels().zip(right.pixels()) {
+    let error = (0..4).map(|c| a.0[c].abs_diff(b.0[c])).max().unwrap_or(0);
+    total_error += (0..4).map(|c| a.0[c].abs_diff(b.0[c]) as u64).sum::<u64>();
+    max_error = max_error.max(error);
+    differing_pixels += (error != 0) as usize;
+  };
+
+  LayerDiff::Compared {
+    level,
+    max_error,
+    mean_error: total_error as f64 / left.as_raw().len().max(1) as f64,
+    differing_pixels,
+    psnr: image_psnr(left, right)
+  }
+}
+
+impl Dds {
+  /// Compares two files structurally, reporting which header fields differ and how far apart
+  /// the pixels of each layer are. Unlike `==`, this tells how close two files are, which is
+  /// useful for checking that re-encoding an asset only changed it as much as expected.
+  pub fn diff(&self, other: &Dds) -> DdsDiff {
+    DdsDiff {
+      header_fields: diff_headers(&self.header, &other.header),
+      layer_counts: (self.layers.len(), other.layers.len()),
+      layers: self.layers.iter().zip(other.layers.iter()).enumerate()
+        .map(|(level, (left, right))| diff_layer(level, left, right))
+        .collect()
+    }
+  }
+}
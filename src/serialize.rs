@@ -0,0 +1,143 @@
+use image::RgbaImage;
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::Compression;
+
+use std::convert::TryFrom;
+use std::fmt;
+
+// Known compressions are written as their name, and other four-ccs as their four characters if they're
+// printable. Older writers store a numeric `D3DFORMAT` value in the four-cc, which is written as that number.
+impl Serialize for Compression {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    match self {
+      Compression::Other(bytes) if !bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') => {
+        serializer.serialize_u32(u32::from_le_bytes(*bytes))
+      },
+      compression => serializer.collect_str(compression)
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for Compression {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Compression, D::Error> {
+    deserializer.deserialize_any(CompressionVisitor)
+  }
+}
+
+struct CompressionVisitor;
+
+impl<'de> Visitor<'de> for CompressionVisitor {
+  type Value = Compression;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "a compression name, a four-cc or a D3DFORMAT number")
+  }
+
+  fn visit_str<E: de::Error>(self, value: &str) -> Result<Compression, E> {
+    match value {
+      "DXT1A" => Ok(Compression::DXT1A),
+      "None" => Ok(Compression::None),
+      _ => match <[u8; 4]>::try_from(value.as_bytes()) {
+        Ok(bytes) if bytes != [0; 4] => Ok(Compression::from_bytes(bytes)),
+        _ => Err(E::invalid_value(de::Unexpected::Str(value), &self))
+      }
+    }
+  }
+
+  fn visit_u64<E: de::Error>(self, value: u64) -> Result<Compression, E> {
+    match u32::try_from(value) {
+      Ok(value) => Ok(Compression::Other(value.to_le_bytes())),
+      Err(_) => Err(E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+  }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Encodes bytes as standard, padded base64
+fn base64_encode(bytes: &[u8]) -> String {
+  let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let group = chunk.iter().enumerate().fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      match i <= chunk.len() {
+        true => out.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char),
+        false => out.push('=')
+      };
+    };
+  };
+
+  out
+}
+
+// Decodes standard base64, with or without padding
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+  let text = text.trim_end_matches('=').as_bytes();
+  let mut out = Vec::with_capacity(text.len() * 3 / 4);
+  for chunk in text.chunks(4) {
+    if chunk.len() == 1 {
+      return None;
+    };
+
+    let mut group = 0u32;
+    for (i, &c) in chunk.iter().enumerate() {
+      let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+      group |= value << (18 - 6 * i);
+    };
+
+    out.extend_from_slice(&group.to_be_bytes()[1..chunk.len()]);
+  };
+
+  Some(out)
+}
+
+// A single layer, with its RGBA pixels stored as base64
+#[derive(Serialize, Deserialize)]
+struct SerializedLayer {
+  width: u32,
+  height: u32,
+  pixels: String
+}
+
+// Serializes the layers of a `Dds`, see `#[serde(with)]`
+pub mod layers {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(layers: &[RgbaImage], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(layers.iter().map(|layer| SerializedLayer {
+      width: layer.width(),
+      height: layer.height(),
+      pixels: base64_encode(layer.as_raw())
+    }))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<RgbaImage>, D::Error> {
+    Vec::<SerializedLayer>::deserialize(deserializer)?.into_iter()
+      .map(|layer| {
+        let pixels = base64_decode(&layer.pixels)
+          .ok_or_else(|| de::Error::custom("layer pixels are not valid base64"))?;
+        RgbaImage::from_raw(layer.width, layer.height, pixels)
+          .ok_or_else(|| de::Error::custom("layer pixels do not match its dimensions"))
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_base64_round_trip() {
+    for (bytes, text) in [(&b""[..], ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foobar", "Zm9vYmFy")].iter() {
+      assert_eq!(base64_encode(bytes), *text);
+      assert_eq!(base64_decode(text).as_deref(), Some(*bytes));
+    };
+
+    assert_eq!(base64_decode("Zm9vY"), None);
+    assert_eq!(base64_decode("Zm9v!A=="), None);
+  }
+}
@@ -0,0 +1,60 @@
+use image::RgbaImage;
+
+use crate::format::decode_layers;
+use crate::{DecodeError, DecodeOptions, Header, TextureKind};
+
+use std::io::Read;
+
+/// Represents a parsed volume texture DDS file, with the slices of each mipmap level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+  /// The parsed DDS header
+  pub header: Header,
+  /// The slices of each mipmap level, from front to back. Every level has half as many
+  /// slices as the one before it, down to a single slice, see [`Header::level_depth`].
+  pub levels: Vec<Vec<RgbaImage>>
+}
+
+impl Volume {
+  /// Decodes a volume texture into the slices of each of its mipmap levels.
+  /// Fails with [`DecodeError::NotVolume`] if the file isn't a volume texture.
+  pub fn decode<R: Read>(reader: R) -> Result<Volume, DecodeError> {
+    Volume::decode_with(reader, &DecodeOptions::default())
+  }
+
+  /// Decodes a volume texture into the slices of each of its mipmap levels, using the given options.
+  pub fn decode_with<R: Read>(mut reader: R, options: &DecodeOptions) -> Result<Volume, DecodeError> {
+    let header = Header::decode_with(&mut reader, options)?;
+    if header.texture_kind() != TextureKind::Volume {
+      return Err(DecodeError::NotVolume);
+    };
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    // Each slice is stored like the main image of a 2D texture, with every slice
+    // of a level in turn, so decode them one at a time with a single level header
+    let slice_options = DecodeOptions { max_level: None, ..options.clone() };
+    let level_count = header.mipmap_levels() as usize;
+    let mut levels = Vec::with_capacity(level_count);
+    for (level, (offset, size)) in header.layer_offsets()?.into_iter().enumerate() {
+      if options.max_level.is_some_and(|max_level| level > max_level) {
+        break;
+      };
+
+      let level_header = header.level_header(level);
+      let depth = header.level_depth(level) as usize;
+      let slice_size = size / depth;
+      let mut slices = Vec::with_capacity(depth);
+      for slice in 0..depth {
+        let start = (offset + slice * slice_size).min(buf.len());
+        let end = (start + slice_size).min(buf.len());
+        slices.extend(decode_layers(&level_header, &buf[start..end], &slice_options)?);
+      };
+
+      levels.push(slices);
+    };
+
+    Ok(Volume { header, levels })
+  }
+}
@@ -0,0 +1,54 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use dds::Dds;
+use image::Rgba;
+
+// Recursively collects every `.dds` file under `dir`
+fn find_dds_files(dir: &Path, paths: &mut Vec<PathBuf>) {
+  for entry in fs::read_dir(dir).unwrap() {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      find_dds_files(&path, paths);
+    } else if path.extension().is_some_and(|extension| extension == "dds") {
+      paths.push(path);
+    };
+  };
+}
+
+// Returns every sample under `./samples` paired with its reference `.png`, sorted by path.
+// Panics if a sample has no reference, so that new samples can't go untested by accident.
+pub fn sample_pairs() -> Vec<(PathBuf, PathBuf)> {
+  let mut paths = Vec::new();
+  find_dds_files(Path::new("./samples"), &mut paths);
+  paths.sort();
+
+  paths.into_iter()
+    .map(|dds_path| {
+      let png_path = dds_path.with_extension("png");
+      assert!(png_path.is_file(), "sample {} has no reference png", dds_path.display());
+      (dds_path, png_path)
+    })
+    .collect()
+}
+
+// Returns the path of every sample under `./samples`, sorted by path
+pub fn sample_paths() -> Vec<PathBuf> {
+  sample_pairs().into_iter().map(|(dds_path, _)| dds_path).collect()
+}
+
+pub fn compare_dds_to_png(dds_path: &Path, png_path: &Path) {
+  let mut reader = BufReader::new(File::open(dds_path).unwrap());
+  let dds = Dds::decode(&mut reader).unwrap();
+
+  let img = image::open(png_path).unwrap();
+  let img = img.into_rgba8();
+
+  for (x, y, Rgba(pixel)) in img.enumerate_pixels() {
+    let Rgba(other_pixel) = dds.layers[0].get_pixel(x, y);
+    assert_eq!(pixel, other_pixel, "pixel ({}, {}) of {} differs from its reference", x, y, dds_path.display());
+  }
+
+  assert_eq!(img, dds.layers[0]);
+}
@@ -1,25 +1,175 @@
 extern crate dds;
 extern crate image;
 
-use std::fs::File;
-use std::io::BufReader;
+mod common;
 
-use dds::Dds;
-use image::{Rgba, RgbaImage};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
 
-fn compare_dds_to_png(dds_path: String, png_path: String) {
-  let mut reader = BufReader::new(File::open(dds_path).unwrap());
-  let dds = Dds::decode(&mut reader).unwrap();
+use dds::{Channel, ChannelOrder, ColorSpace, Compression, CreatorHint, CubeFace, Cubemap, CubemapDecoder, EdgePad, EncodeError, EncodeOptions, DecodeError, DecodeOptions, Dds, DdsStreamDecoder, LayerDiff, Diagnostic, MipOrder, DxgiFormat, Header, NativeDds, PixelFormat, RawHeader, RawHeaderDx10, RawPixelFormat, SurfaceLayout, TextureKind, TileMode, Volume};
+use crate::common::{compare_dds_to_png, sample_pairs, sample_paths};
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
 
-  let img = image::open(png_path).unwrap();
-  let img = img.into_rgba8();
+// Counts the allocations made by each thread, so tests can measure allocation churn
+struct CountingAllocator;
 
-  for (x, y, Rgba(pixel)) in img.enumerate_pixels() {
-    let Rgba(other_pixel) = dds.layers[0].get_pixel(x, y);
-    assert_eq!(pixel, other_pixel);
+thread_local! {
+  static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
   }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Returns the number of allocations made on this thread while running `f`
+fn count_allocations<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+  let before = ALLOCATIONS.with(Cell::get);
+  let value = f();
+  (value, ALLOCATIONS.with(Cell::get) - before)
+}
+
+// Builds an uncompressed DDS file in memory from the given pixel format and payload
+fn uncompressed_dds(width: u32, height: u32, rgb_bit_count: u32, masks: [u32; 4], data: &[u8]) -> Vec<u8> {
+  uncompressed_dds_with_pitch(width, height, width * rgb_bit_count / 8, rgb_bit_count, masks, data)
+}
+
+fn uncompressed_dds_with_pitch(width: u32, height: u32, pitch: u32, rgb_bit_count: u32, masks: [u32; 4], data: &[u8]) -> Vec<u8> {
+  let header = RawHeader {
+    size: 124,
+    flags: 0x100F,
+    height,
+    width,
+    pitch_or_linear_size: pitch,
+    depth: 0,
+    mipmap_count: 0,
+    reserved: [0; 11],
+    pixel_format: RawPixelFormat {
+      size: 32,
+      flags: if masks[3] != 0 { 0x41 } else { 0x40 },
+      four_cc: [0; 4],
+      rgb_bit_count,
+      red_bit_mask: masks[0],
+      green_bit_mask: masks[1],
+      blue_bit_mask: masks[2],
+      alpha_bit_mask: masks[3]
+    },
+    caps: 0x1000,
+    caps2: 0,
+    caps3: 0,
+    caps4: 0,
+    reserved2: 0
+  };
+
+  let mut bytes = Vec::new();
+  header.encode(&mut bytes).unwrap();
+  bytes.extend_from_slice(data);
+  bytes
+}
+
+// Builds the faces of a cubemap with a 4x4 -> 2x2 -> 1x1 mipmap chain, giving every face and level its own color
+fn cubemap_faces() -> [Vec<RgbaImage>; 6] {
+  std::array::from_fn(|face| {
+    [4, 2, 1].iter()
+      .map(|&size| RgbaImage::from_pixel(size, size, Rgba([face as u8 * 40, 255 - face as u8 * 40, size as u8, 255])))
+      .collect()
+  })
+}
+
+// Builds a DDS file with the given four-cc in memory
+fn four_cc_dds(width: u32, height: u32, four_cc: [u8; 4], data: &[u8]) -> Vec<u8> {
+  let bytes = uncompressed_dds(width, height, 0, [0; 4], data);
+  with_raw_header(&bytes, |raw_header| {
+    raw_header.flags = 0x81007;
+    raw_header.pitch_or_linear_size = data.len() as u32;
+    raw_header.pixel_format.flags = 0x4;
+    raw_header.pixel_format.four_cc = four_cc;
+  })
+}
+
+// Builds a DDS file with a DX10 extended header in memory
+fn dx10_dds(width: u32, height: u32, dxgi_format: DxgiFormat, data: &[u8]) -> Vec<u8> {
+  let header = RawHeader {
+    size: 124,
+    flags: 0x1007,
+    height,
+    width,
+    pitch_or_linear_size: 0,
+    depth: 0,
+    mipmap_count: 0,
+    reserved: [0; 11],
+    pixel_format: RawPixelFormat {
+      size: 32,
+      flags: 0x4,
+      four_cc: *b"DX10",
+      rgb_bit_count: 0,
+      red_bit_mask: 0,
+      green_bit_mask: 0,
+      blue_bit_mask: 0,
+      alpha_bit_mask: 0
+    },
+    caps: 0x1000,
+    caps2: 0,
+    caps3: 0,
+    caps4: 0,
+    reserved2: 0
+  };
+
+  let header_dx10 = RawHeaderDx10 {
+    dxgi_format: dxgi_format.to_u32(),
+    resource_dimension: 3,
+    misc_flag: 0,
+    array_size: 1,
+    misc_flags2: 0
+  };
+
+  let mut bytes = Vec::new();
+  header.encode(&mut bytes).unwrap();
+  header_dx10.encode(&mut bytes).unwrap();
+  bytes.extend_from_slice(data);
+  bytes
+}
+
+// Applies `f` to the raw header of an encoded file, leaving the rest of the file untouched
+fn with_raw_header<F: FnOnce(&mut RawHeader)>(bytes: &[u8], f: F) -> Vec<u8> {
+  let mut raw_header = RawHeader::decode(bytes).unwrap();
+  f(&mut raw_header);
+
+  let mut new_bytes = Vec::new();
+  raw_header.encode(&mut new_bytes).unwrap();
+  new_bytes.extend_from_slice(&bytes[128..]);
+  new_bytes
+}
 
-  assert_eq!(img, dds.layers[0]);
+// An 8x8x8 uncompressed volume texture with 4 mipmap levels, every slice
+// filled with its own index, starting from the front slice of the first level
+fn volume_dds() -> Vec<u8> {
+  let (mut data, mut slice) = (Vec::new(), 0u8);
+  for level in 0..4 {
+    for _ in 0..(8 >> level) {
+      data.extend((0..(8 >> level) * (8 >> level)).flat_map(|_| vec![slice, slice, slice, 255]));
+      slice += 1;
+    };
+  };
+
+  with_raw_header(&uncompressed_dds(8, 8, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+    raw_header.flags |= 0x820000;
+    raw_header.depth = 8;
+    raw_header.mipmap_count = 4;
+    raw_header.caps |= 0x400008;
+    raw_header.caps2 = 0x200000;
+  })
 }
 
 #[cfg(test)]
@@ -54,23 +204,2281 @@ mod tests {
 
   #[test]
   fn test_dds_vs_png() {
-    let filenames = [
-      "dxt1",
-      "dxt5",
-      "qt/DXT1",
-      "qt/DXT2",
-      "qt/DXT3",
-      "qt/DXT4",
-      "qt/DXT5",
-      "qt/A8R8G8B8",
-      "qt/A8R8G8B8.2"
+    let pairs = sample_pairs();
+    assert!(!pairs.is_empty(), "no samples were found");
+
+    for (dds_path, png_path) in pairs.iter() {
+      compare_dds_to_png(dds_path, png_path);
+    }
+  }
+
+  #[cfg(feature = "rayon")]
+  #[test]
+  fn test_decode_many() {
+    let paths = [
+      "./samples/dxt1.dds",
+      "./samples/qt/A8R8G8B8.dds",
+      "./samples/dxt5.dds",
+      "./samples/qt/DXT3.dds"
+    ];
+
+    let results = dds::decode_many(&paths);
+    assert_eq!(results.len(), paths.len());
+
+    for (path, result) in paths.iter().zip(results) {
+      let expected = Dds::decode(BufReader::new(File::open(path).unwrap())).unwrap();
+      assert_eq!(result.unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn test_decode_r8g8b8() {
+    // Pixels are stored as little-endian B, G, R
+    let data = [0x10, 0x20, 0x30, 0xFF, 0x00, 0x80];
+    let bytes = uncompressed_dds(2, 1, 24, [0xFF0000, 0xFF00, 0xFF, 0], &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::R8G8B8);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([0x30, 0x20, 0x10, 255]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([0x80, 0x00, 0xFF, 255]));
+  }
+
+  #[test]
+  fn test_decode_b8g8r8() {
+    // Pixels are stored as little-endian R, G, B
+    let data = [0x10, 0x20, 0x30, 0xFF, 0x00, 0x80];
+    let bytes = uncompressed_dds(2, 1, 24, [0xFF, 0xFF00, 0xFF0000, 0], &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::B8G8R8);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([0x10, 0x20, 0x30, 255]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([0xFF, 0x00, 0x80, 255]));
+  }
+
+  #[test]
+  fn test_decode_padded_pitch() {
+    let image = RgbaImage::from_raw(4, 4, (0u8..64).collect()).unwrap();
+
+    // Pad each 16 byte row out to a pitch of 24 bytes
+    let mut data = Vec::new();
+    for row in image.as_raw().chunks(16) {
+      data.extend_from_slice(row);
+      data.extend_from_slice(&[0xEE; 8]);
+    }
+
+    let masks = [0xFF, 0xFF00, 0xFF0000, 0xFF000000];
+    let bytes = uncompressed_dds_with_pitch(4, 4, 24, 32, masks, &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert_eq!(dds.header.pitch, Some(24));
+    assert_eq!(image, dds.layers[0]);
+  }
+
+  #[test]
+  fn test_peek_dimensions() {
+    for path in sample_paths() {
+      let bytes = fs::read(path).unwrap();
+      let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+
+      let dimensions = RawHeader::peek_dimensions(&bytes);
+      assert_eq!(dimensions, Some((raw_header.width, raw_header.height)));
+    }
+
+    assert_eq!(RawHeader::peek_dimensions(b"DDS "), None);
+    assert_eq!(RawHeader::peek_dimensions(&[0; 128]), None);
+  }
+
+  #[test]
+  fn test_decode_premultiplied() {
+    let masks = [0xFF, 0xFF00, 0xFF0000, 0xFF000000];
+    let bytes = uncompressed_dds(1, 1, 32, masks, &[255, 0, 0, 128]);
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 0, 0, 128]));
+
+    let options = DecodeOptions { premultiply: true, ..Default::default() };
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([128, 0, 0, 128]));
+  }
+
+  #[test]
+  fn test_decode_dx10() {
+    let bytes = fs::read("./samples/dxt5.dds").unwrap();
+    let expected = Dds::decode(bytes.as_slice()).unwrap();
+
+    let (width, height) = expected.layers[0].dimensions();
+    let bytes = dx10_dds(width, height, DxgiFormat::BC3_UNORM, &bytes[128..]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert_eq!(dds.header.dx10.unwrap().dxgi_format, DxgiFormat::BC3_UNORM);
+    assert_eq!(dds.layers[0], expected.layers[0]);
+
+    let bytes = dx10_dds(1, 1, DxgiFormat::B8G8R8A8_UNORM, &[0x10, 0x20, 0x30, 0x40]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::A8R8G8B8);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([0x30, 0x20, 0x10, 0x40]));
+  }
+
+  #[test]
+  fn test_decode_unsupported_dxgi_format() {
+    let bytes = dx10_dds(4, 4, DxgiFormat::BC7_UNORM, &[0; 16]);
+
+    match Dds::decode(bytes.as_slice()) {
+      Err(DecodeError::UnsupportedDxgiFormat(DxgiFormat::BC7_UNORM)) => (),
+      result => panic!("unexpected result: {:?}", result.map(|dds| dds.header))
+    };
+  }
+
+  #[test]
+  fn test_reserved_round_trip() {
+    let bytes = fs::read("./samples/qt/A8R8G8B8.dds").unwrap();
+    let mut raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    raw_header.reserved = [1, 2, 3, 4, 5, 6, 7, 8, 9, u32::from_le_bytes(*b"NVTT"), 0x20008];
+    raw_header.reserved2 = 0xDEADBEEF;
+
+    let mut encoded = Vec::new();
+    raw_header.encode(&mut encoded).unwrap();
+    let header = Header::decode(encoded.as_slice()).unwrap();
+    assert_eq!(header.reserved, raw_header.reserved);
+    assert_eq!(header.reserved2, raw_header.reserved2);
+
+    let mut reencoded = Vec::new();
+    header.to_raw_header().encode(&mut reencoded).unwrap();
+    assert_eq!(Header::decode(reencoded.as_slice()).unwrap(), header);
+  }
+
+  #[test]
+  fn test_header_size() {
+    let bytes = dx10_dds(1, 1, DxgiFormat::R8G8B8A8_UNORM, &[1, 2, 3, 4]);
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.size(), 148);
+
+    // The DX10 header must be consumed before the payload, otherwise the pixel would be garbage
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([1, 2, 3, 4]));
+
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &[1, 2, 3, 4]);
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.size(), 128);
+
+    // A DX10 file missing its extended header can't be decoded
+    let bytes = dx10_dds(1, 1, DxgiFormat::R8G8B8A8_UNORM, &[]);
+    assert!(Dds::decode(&bytes[..140]).is_err());
+  }
+
+  #[test]
+  fn test_decode_typeless() {
+    let bytes = fs::read("./samples/dxt5.dds").unwrap();
+    let (width, height) = RawHeader::peek_dimensions(&bytes).unwrap();
+
+    let unorm = dx10_dds(width, height, DxgiFormat::BC3_UNORM, &bytes[128..]);
+    let typeless = dx10_dds(width, height, DxgiFormat::BC3_TYPELESS, &bytes[128..]);
+    let unorm = Dds::decode(unorm.as_slice()).unwrap();
+    let typeless = Dds::decode(typeless.as_slice()).unwrap();
+    assert_eq!(typeless.layers, unorm.layers);
+
+    let bytes = dx10_dds(1, 1, DxgiFormat::R8G8B8A8_TYPELESS, &[1, 2, 3, 4]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([1, 2, 3, 4]));
+  }
+
+  #[test]
+  fn test_decode_r16g16_unorm() {
+    // R = 0xFFFF, G = 0x8000, stored little-endian
+    let data = [0xFF, 0xFF, 0x00, 0x80];
+
+    let bytes = dx10_dds(1, 1, DxgiFormat::R16G16_UNORM, &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 127, 0, 255]));
+
+    let bytes = uncompressed_dds(1, 1, 32, [0xFFFF, 0xFFFF0000, 0, 0], &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::G16R16);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 127, 0, 255]));
+  }
+
+  #[test]
+  fn test_decode_r16g16_float() {
+    // R = 1.0 (0x3C00), G = 0.5 (0x3800), then R = -2.0 (0xC000), G = 65504.0 (0x7BFF)
+    let data = [0x00, 0x3C, 0x00, 0x38, 0x00, 0xC0, 0xFF, 0x7B];
+
+    let bytes = dx10_dds(2, 1, DxgiFormat::R16G16_FLOAT, &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 128, 0, 255]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([0, 255, 0, 255]));
+  }
+
+  #[test]
+  fn test_pack_atlas() {
+    let images = [
+      RgbaImage::from_pixel(30, 20, Rgba([255, 0, 0, 255])),
+      RgbaImage::from_pixel(16, 40, Rgba([0, 255, 0, 255])),
+      RgbaImage::from_pixel(50, 10, Rgba([0, 0, 255, 255]))
     ];
 
-    for filename in filenames.iter() {
-      compare_dds_to_png(
-        format!("./samples/{}.dds", filename),
-        format!("./samples/{}.png", filename)
-      );
+    let (atlas, rects) = dds::pack_atlas(&images, 2);
+    assert!(atlas.width().is_power_of_two());
+    assert!(atlas.height().is_power_of_two());
+    assert_eq!(rects.len(), images.len());
+
+    for (i, (image, rect)) in images.iter().zip(rects.iter()).enumerate() {
+      assert_eq!((rect.width, rect.height), image.dimensions());
+      assert!(rect.x + rect.width <= atlas.width());
+      assert!(rect.y + rect.height <= atlas.height());
+
+      for other in rects[i + 1..].iter() {
+        assert!(!rect.intersects(other));
+      }
+
+      let view = image::imageops::crop_imm(&atlas, rect.x, rect.y, rect.width, rect.height).to_image();
+      assert_eq!(&view, image);
+    }
+  }
+
+  #[test]
+  fn test_decode_implicit_mipmap_count() {
+    // A 4x2 image, followed by 2x1 and 1x1 mipmaps
+    let data = (0u8..44).collect::<Vec<u8>>();
+    let bytes = uncompressed_dds(4, 2, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &data);
+    let bytes = with_raw_header(&bytes, |raw_header| {
+      raw_header.mipmap_count = 0;
+      raw_header.caps |= dds::flags::DDSCAPS_COMPLEX | dds::flags::DDSCAPS_MIPMAP;
+    });
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    let dimensions = dds.layers.iter().map(|layer| layer.dimensions()).collect::<Vec<_>>();
+    assert_eq!(dimensions, [(4, 2), (2, 1), (1, 1)]);
+    assert_eq!(dds.layers[1].as_raw().as_slice(), &data[32..40]);
+    assert_eq!(dds.layers[2].as_raw().as_slice(), &data[40..44]);
+  }
+
+  #[test]
+  fn test_encode_l8() {
+    let image = GrayImage::from_raw(4, 2, vec![0, 16, 32, 64, 128, 192, 224, 255]).unwrap();
+
+    let mut bytes = Vec::new();
+    Dds::encode_l8(&mut bytes, &image).unwrap();
+    assert_eq!(bytes.len(), 128 + 8);
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::L8);
+    for (x, y, pixel) in image.enumerate_pixels() {
+      let l = pixel.0[0];
+      assert_eq!(dds.layers[0].get_pixel(x, y), &Rgba([l, l, l, 255]));
+    }
+  }
+
+  #[test]
+  fn test_decode_native() {
+    let bytes = dx10_dds(1, 1, DxgiFormat::R16G16_UNORM, &[0xFF, 0xFF, 0x00, 0x80]);
+    match Dds::decode_native(bytes.as_slice()).unwrap() {
+      NativeDds::U16(layers) => assert_eq!(layers[0].get_pixel(0, 0), &Rgba([0xFFFF, 0x8000, 0, 0xFFFF])),
+      layers => panic!("expected 16-bit layers, got {:?}", layers)
+    };
+
+    let bytes = dx10_dds(1, 1, DxgiFormat::R16G16_FLOAT, &[0x00, 0xC0, 0x00, 0x38]);
+    match Dds::decode_native(bytes.as_slice()).unwrap() {
+      NativeDds::F32(layers) => assert_eq!(layers[0].get_pixel(0, 0), &Rgba([-2.0, 0.5, 0.0, 1.0])),
+      layers => panic!("expected float layers, got {:?}", layers)
+    };
+
+    let file = File::open("./samples/qt/A8R8G8B8.dds").unwrap();
+    let expected = Dds::decode(BufReader::new(file)).unwrap();
+    let file = File::open("./samples/qt/A8R8G8B8.dds").unwrap();
+    match Dds::decode_native(BufReader::new(file)).unwrap() {
+      NativeDds::U8(layers) => assert_eq!(layers, expected.layers),
+      layers => panic!("expected 8-bit layers, got {:?}", layers)
+    };
+  }
+
+  #[test]
+  fn test_decode_channel_order() {
+    let argb = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &[0x30, 0x20, 0x10, 0x40]);
+    let abgr = uncompressed_dds(1, 1, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &[0x10, 0x20, 0x30, 0x40]);
+
+    let argb_dds = Dds::decode(argb.as_slice()).unwrap();
+    let abgr_dds = Dds::decode(abgr.as_slice()).unwrap();
+    assert_eq!(argb_dds.header.pixel_format, dds::PixelFormat::A8R8G8B8);
+    assert_eq!(abgr_dds.header.pixel_format, dds::PixelFormat::A8B8G8R8);
+    assert_eq!(argb_dds.layers, abgr_dds.layers);
+    assert_eq!(argb_dds.layers[0].get_pixel(0, 0), &Rgba([0x10, 0x20, 0x30, 0x40]));
+
+    let options = DecodeOptions { output_order: ChannelOrder::Bgra, ..Default::default() };
+    let dds = Dds::decode_with(argb.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([0x30, 0x20, 0x10, 0x40]));
+  }
+
+  #[test]
+  fn test_stream_decoder() {
+    for filename in ["dxt1", "qt/DXT5", "qt/A8R8G8B8"].iter() {
+      let bytes = fs::read(format!("./samples/{}.dds", filename)).unwrap();
+      let expected = Dds::decode(bytes.as_slice()).unwrap();
+
+      let mut decoder = DdsStreamDecoder::new();
+      let mut result = None;
+      for chunk in bytes.chunks(7) {
+        assert!(result.is_none());
+        decoder.feed(chunk);
+        result = decoder.try_finish();
+      }
+
+      assert_eq!(result.unwrap().unwrap(), expected);
+    }
+
+    // Invalid files are rejected as soon as the magic bytes arrive
+    let mut decoder = DdsStreamDecoder::new();
+    decoder.feed(b"PN");
+    assert!(decoder.try_finish().is_none());
+    decoder.feed(b"G ");
+    assert!(matches!(decoder.try_finish(), Some(Err(DecodeError::InvalidMagicBytes(_)))));
+  }
+
+  #[test]
+  fn test_pixel_format_size() {
+    let image = RgbaImage::from_raw(2, 2, vec![0u8; 16]).unwrap();
+
+    let mut bytes = Vec::new();
+    Dds::encode_uncompressed(&mut bytes, &image).unwrap();
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    assert_eq!(raw_header.pixel_format.size, 32);
+    assert_eq!(raw_header.validate(true).unwrap(), []);
+
+    let bytes = with_raw_header(&bytes, |raw_header| raw_header.pixel_format.size = 0);
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    assert_eq!(raw_header.validate(false).unwrap(), [Diagnostic::InvalidPixelFormatSize(0)]);
+
+    // Lenient decoding ignores the bad size, strict decoding rejects it
+    assert_eq!(Dds::decode(bytes.as_slice()).unwrap().layers[0], image);
+    let options = DecodeOptions { strict: true, ..Default::default() };
+    match Dds::decode_with(bytes.as_slice(), &options) {
+      Err(DecodeError::InvalidPixelFormatSize(0)) => (),
+      result => panic!("unexpected result: {:?}", result.map(|dds| dds.header))
+    };
+  }
+
+  #[test]
+  fn test_decode_at_offset() {
+    let bytes = fs::read("./samples/dxt1.dds").unwrap();
+    let expected = Dds::decode(bytes.as_slice()).unwrap();
+
+    let mut container = vec![0xAB; 100];
+    container.extend_from_slice(&bytes);
+    container.extend_from_slice(&[0xCD; 50]);
+
+    // Whatever follows the texture is left for the caller to read
+    let mut reader = Cursor::new(container);
+    let dds = Dds::decode_at_offset(&mut reader, 100).unwrap();
+    assert_eq!(dds, expected);
+    assert_eq!(reader.position(), 100 + bytes.len() as u64);
+  }
+
+  #[test]
+  fn test_image_metrics() {
+    let a = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+    assert_eq!(dds::image_psnr(&a, &a), f64::INFINITY);
+    assert!((dds::image_ssim(&a, &a) - 1.0).abs() < 1e-9);
+
+    // A single channel of a single pixel off by 255, out of 256 channels total
+    let mut b = a.clone();
+    b.put_pixel(3, 3, Rgba([255, 0, 0, 255]));
+    let psnr = dds::image_psnr(&a, &b);
+    assert!((psnr - 10.0 * 256f64.log10()).abs() < 1e-9);
+    assert!(dds::image_ssim(&a, &b) < 1.0);
+  }
+
+  #[test]
+  fn test_decode_dxt1a() {
+    // Black and white endpoints with color0 <= color1, so index 3 is transparent.
+    // Every row holds the indices 0, 1, 2, 3 from left to right.
+    let block = [0x00, 0x00, 0xFF, 0xFF, 0xE4, 0xE4, 0xE4, 0xE4];
+    let expected = [[0, 0, 0, 255], [255, 255, 255, 255], [127, 127, 127, 255], [0, 0, 0, 0]];
+
+    let bytes = four_cc_dds(4, 4, *b"DX1A", &block);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.compression, dds::Compression::DXT1A);
+    for (x, y, Rgba(pixel)) in dds.layers[0].enumerate_pixels() {
+      assert_eq!(pixel, &expected[x as usize], "pixel ({}, {})", x, y);
     }
+
+    // Plain DXT1 without an alpha pixel format stays opaque
+    let bytes = four_cc_dds(4, 4, *b"DXT1", &block);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert!(dds.layers[0].pixels().all(|pixel| pixel.0[3] == 255));
+  }
+
+  #[test]
+  fn test_decode_dxt_allocations() {
+    // 64x64 down to 1x1 is 7 layers, taking 256 + 64 + 16 + 4 + 1 + 1 + 1 blocks
+    let data = vec![0xAA; 343 * 16];
+    let bytes = with_raw_header(&four_cc_dds(64, 64, *b"DXT5", &data), |raw_header| {
+      raw_header.flags |= 0x20000;
+      raw_header.mipmap_count = 7;
+    });
+
+    let (dds, allocations) = count_allocations(|| Dds::decode(bytes.as_slice()).unwrap());
+    assert_eq!(dds.layers.len(), 7);
+
+    // One allocation per output layer, plus a handful for the payload, layer list and scratch
+    // buffer. Allocating intermediate buffers per block and per layer used to take 586.
+    assert!(allocations <= 12, "decoding made {} allocations", allocations);
+  }
+
+  #[test]
+  fn test_encode_cubemap() {
+    let faces = cubemap_faces();
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &faces, dds::Compression::None).unwrap();
+
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    assert_eq!(raw_header.caps, 0x401008);
+    assert_eq!(raw_header.caps2, 0xFE00);
+    assert_eq!(raw_header.mipmap_count, 3);
+
+    let cubemap = Cubemap::decode(bytes.as_slice()).unwrap();
+    assert!(cubemap.header.is_cubemap());
+    assert_eq!(cubemap.faces, faces);
+    assert_eq!(cubemap.face(CubeFace::NegativeY), &faces[3][..]);
+
+    // Plain decoding keeps every face's mipmap chain in file order
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers, faces.concat());
+
+    let mut mismatched = faces.clone();
+    mismatched[5].pop();
+    assert!(matches!(
+      Dds::encode_cubemap(Vec::new(), &mismatched, dds::Compression::None),
+      Err(EncodeError::MismatchedCubemapFaces)
+    ));
+
+    assert!(matches!(Cubemap::decode(File::open("./samples/dxt1.dds").unwrap()), Err(DecodeError::NotCubemap)));
+  }
+
+  #[test]
+  fn test_encode_cubemap_dxt5() {
+    let faces = cubemap_faces();
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &faces, Compression::DXT5).unwrap();
+
+    // Compressed cubemaps give the linear size of the main image of a face instead of a pitch
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    assert_eq!((raw_header.caps, raw_header.caps2, raw_header.mipmap_count), (0x401008, 0xFE00, 3));
+    assert_eq!((raw_header.flags & 0x80008, raw_header.pitch_or_linear_size), (0x80000, 16));
+    assert_eq!(raw_header.pixel_format.four_cc, *b"DXT5");
+    assert_eq!(bytes.len(), 128 + 6 * 3 * 16);
+
+    // Colors are quantized to 5 and 6 bits, so they only come back approximately
+    let cubemap = Cubemap::decode(bytes.as_slice()).unwrap();
+    for (decoded, expected) in cubemap.faces.iter().flatten().zip(faces.iter().flatten()) {
+      assert_eq!(decoded.dimensions(), expected.dimensions());
+      for (a, b) in decoded.pixels().zip(expected.pixels()) {
+        assert!(a.0.iter().zip(b.0.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 8), "{:?} != {:?}", a, b);
+      };
+    };
+
+    assert!(matches!(
+      Dds::encode_cubemap(Vec::new(), &faces, Compression::Other(*b"ATI2")),
+      Err(EncodeError::UnsupportedCompression(Compression::Other(_)))
+    ));
+  }
+
+  #[test]
+  fn test_decode_snorm() {
+    let data = [0x80, 0x81, 0x00, 0x7F, 0xFF, 0x01, 0x40, 0xC0];
+
+    let bytes = dx10_dds(2, 1, DxgiFormat::R8G8B8A8_SNORM, &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([0, 1, 128, 255]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([127, 129, 192, 64]));
+
+    // The native decode keeps the sign, with both -128 and -127 mapping to -1
+    match Dds::decode_native(bytes.as_slice()).unwrap() {
+      NativeDds::F32(layers) => {
+        assert_eq!(layers[0].get_pixel(0, 0), &Rgba([-1.0, -1.0, 0.0, 1.0]));
+        assert_eq!(layers[0].get_pixel(1, 0), &Rgba([-1.0 / 127.0, 1.0 / 127.0, 64.0 / 127.0, -64.0 / 127.0]));
+      },
+      other => panic!("expected float layers, got {:?}", other)
+    };
+
+    // Missing channels are black and opaque
+    let bytes = dx10_dds(1, 1, DxgiFormat::R8G8_SNORM, &[0x7F, 0x80]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+  }
+
+  #[test]
+  fn test_estimate_ratio() {
+    use dds::Compression;
+
+    let image = RgbaImage::new(256, 256);
+    assert_eq!(Dds::estimate_ratio(&image, Compression::DXT1), Some(1.0 / 8.0));
+    assert_eq!(Dds::estimate_ratio(&image, Compression::DXT5), Some(1.0 / 4.0));
+    assert_eq!(Dds::estimate_ratio(&image, Compression::None), None);
+    assert_eq!(Dds::estimate_ratio(&image, Compression::Other(*b"ATI2")), None);
+
+    // Partial blocks are stored in full, so a 2x2 image still takes an entire block
+    assert_eq!(Compression::DXT1.surface_bytes(2, 2), Some(8));
+    assert_eq!(Dds::estimate_ratio(&RgbaImage::new(2, 2), Compression::DXT1), Some(0.5));
+  }
+
+  #[test]
+  fn test_decode_headerless() {
+    let bytes = fs::read("./samples/dxt5.dds").unwrap();
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    assert!(matches!(Dds::decode(&bytes[4..]), Err(DecodeError::InvalidMagicBytes(_))));
+    assert_eq!(Dds::decode_headerless(&bytes[4..]).unwrap(), dds);
+  }
+
+  #[test]
+  fn test_channel_bits() {
+    let bytes = uncompressed_dds(1, 1, 16, [0xF800, 0x7E0, 0x1F, 0x0], &[0; 2]);
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.channel_bits(), [5, 6, 5, 0]);
+    assert!(!header.has_alpha());
+
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &[0; 4]);
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.channel_bits(), [8, 8, 8, 8]);
+    assert!(header.has_alpha());
+
+    let header = Header::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    assert_eq!(header.channel_bits(), [0; 4]);
+    assert!(header.has_alpha());
+  }
+
+  #[test]
+  fn test_decode_max_level() {
+    let options = DecodeOptions { max_level: Some(2), ..Default::default() };
+
+    let data = vec![0xAA; 343 * 16];
+    let bytes = with_raw_header(&four_cc_dds(64, 64, *b"DXT5", &data), |raw_header| {
+      raw_header.flags |= 0x20000;
+      raw_header.mipmap_count = 7;
+    });
+
+    let full = Dds::decode(bytes.as_slice()).unwrap();
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers.len(), 3);
+    assert_eq!(dds.layers[..], full.layers[..3]);
+
+    // Uncompressed 4x4 -> 2x2 -> 1x1, only keeping the main image
+    let options = DecodeOptions { max_level: Some(0), ..Default::default() };
+    let data = (0..21 * 4).map(|i| i as u8).collect::<Vec<u8>>();
+    let bytes = with_raw_header(&uncompressed_dds(4, 4, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &data), |raw_header| {
+      raw_header.mipmap_count = 3;
+    });
+
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers.len(), 1);
+    assert_eq!(dds.layers[0].as_raw()[..], data[..64]);
+  }
+
+  #[test]
+  fn test_raw_header_to_bytes() {
+    let bytes = fs::read("./samples/dxt1.dds").unwrap();
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+
+    let header_bytes = raw_header.to_bytes();
+    assert_eq!(header_bytes[..], bytes[..128]);
+    assert_eq!(RawHeader::decode(&header_bytes[..]).unwrap(), raw_header);
+
+    let mut encoded = Vec::new();
+    raw_header.encode(&mut encoded).unwrap();
+    assert_eq!(encoded[..], header_bytes[..]);
+  }
+
+  #[test]
+  fn test_decode_a8r3g3b2_a4l4() {
+    let bytes = uncompressed_dds(2, 1, 16, [0xE0, 0x1C, 0x3, 0xFF00], &[0xE3, 0x80, 0x49, 0xFF]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::A8R3G3B2);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 0, 255, 128]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([72, 72, 85, 255]));
+
+    let bytes = uncompressed_dds(2, 1, 8, [0xF, 0x0, 0x0, 0xF0], &[0x3C, 0xF0]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, dds::PixelFormat::A4L4);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([204, 204, 204, 51]));
+    assert_eq!(dds.layers[0].get_pixel(1, 0), &Rgba([0, 0, 0, 255]));
+  }
+
+  #[test]
+  fn test_cubemap_decoder() {
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &cubemap_faces(), dds::Compression::None).unwrap();
+    let cubemap = Cubemap::decode(bytes.as_slice()).unwrap();
+
+    let mut decoder = CubemapDecoder::new(Cursor::new(&bytes)).unwrap();
+    assert_eq!(decoder.header, cubemap.header);
+    assert_eq!(decoder.face(CubeFace::PositiveX, 0).unwrap(), cubemap.face(CubeFace::PositiveX)[0]);
+    assert_eq!(decoder.face(CubeFace::NegativeZ, 1).unwrap(), cubemap.face(CubeFace::NegativeZ)[1]);
+    assert_eq!(decoder.face(CubeFace::PositiveY, 2).unwrap(), cubemap.face(CubeFace::PositiveY)[2]);
+    assert!(matches!(
+      decoder.face(CubeFace::PositiveX, 3),
+      Err(DecodeError::MipmapLevelOutOfRange { level: 3, count: 3 })
+    ));
+  }
+
+  #[test]
+  fn test_image_decoder() {
+    use image::{ColorType, DynamicImage, ImageDecoder};
+
+    let dds = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+
+    let decoder = dds::DdsImageDecoder::new(BufReader::new(File::open("./samples/dxt5.dds").unwrap())).unwrap();
+    assert_eq!(decoder.dimensions(), dds.layers[0].dimensions());
+    assert_eq!(decoder.color_type(), ColorType::Rgba8);
+
+    let image = DynamicImage::from_decoder(decoder).unwrap();
+    assert_eq!(image.into_rgba8(), dds.layers[0]);
+  }
+
+  #[test]
+  fn test_decode_guess_unknown() {
+    let options = DecodeOptions { guess_unknown: true, ..Default::default() };
+
+    for path in ["./samples/dxt1.dds", "./samples/dxt5.dds"] {
+      let bytes = fs::read(path).unwrap();
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+      let corrupted = with_raw_header(&bytes, |raw_header| raw_header.pixel_format.four_cc = *b"XYZW");
+      assert!(matches!(Dds::decode(corrupted.as_slice()), Err(DecodeError::UnsupportedCompression(_))));
+      assert_eq!(Dds::decode_with(corrupted.as_slice(), &options).unwrap().layers, dds.layers);
+
+      // Payloads that don't fit either layout can't be guessed
+      let truncated = &corrupted[..corrupted.len() - 1];
+      assert!(matches!(Dds::decode_with(truncated, &options), Err(DecodeError::UnsupportedCompression(_))));
+    };
+  }
+
+  #[test]
+  fn test_decode_short_block() {
+    for four_cc in [*b"DXT3", *b"DXT5"] {
+      let bytes = four_cc_dds(4, 4, four_cc, &[0; 12]);
+      assert!(matches!(
+        Dds::decode(bytes.as_slice()),
+        Err(DecodeError::InvalidBlockLength { expected: 16, found: 12 })
+      ));
+    };
+
+    // Missing blocks are reported the same way
+    let bytes = four_cc_dds(8, 4, *b"DXT1", &[0; 8]);
+    assert!(matches!(
+      Dds::decode(bytes.as_slice()),
+      Err(DecodeError::InvalidBlockLength { expected: 8, found: 0 })
+    ));
+  }
+
+  #[test]
+  fn test_decode_planar() {
+    // A 4x4 NV12 image is 16 bytes of luma followed by 8 bytes of interleaved chroma
+    let bytes = dx10_dds(4, 4, DxgiFormat::NV12, &[0; 24]);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::UnsupportedLayout(DxgiFormat::NV12))));
+    assert!(DxgiFormat::P010.is_planar());
+    assert!(!DxgiFormat::YUY2.is_planar());
+  }
+
+  #[test]
+  fn test_iter_uncompressed_pixels() {
+    // 3x2 R8G8B8 with rows padded out to 12 bytes
+    let data = [
+      0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0, 0, 0,
+      0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x01, 0x02, 0x03, 0, 0, 0
+    ];
+    let bytes = uncompressed_dds_with_pitch(3, 2, 12, 24, [0xFF0000, 0xFF00, 0xFF, 0x0], &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    let pixels = dds::iter_uncompressed_pixels(&header, &bytes[header.size()..]).unwrap().collect::<Vec<_>>();
+    assert_eq!(pixels.len(), 3 * 2);
+    assert!(pixels.iter().eq(dds.layers[0].pixels().map(|pixel| &pixel.0)));
+
+    let header = Header::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert!(dds::iter_uncompressed_pixels(&header, &[]).is_err());
+  }
+
+  #[test]
+  fn test_row_pitch() {
+    let row_pitch = |width, bit_count| {
+      let bytes = uncompressed_dds(width, 1, bit_count, [0; 4], &[]);
+      Header::decode(bytes.as_slice()).unwrap().row_pitch()
+    };
+
+    assert_eq!(row_pitch(3, 32), 12);
+    assert_eq!(row_pitch(5, 24), 15);
+    assert_eq!(row_pitch(7, 16), 14);
+    // Sub-byte pixels are rounded up to a whole byte
+    assert_eq!(row_pitch(3, 4), 2);
+    assert_eq!(row_pitch(9, 1), 2);
+    // Malformed headers can ask for more than 32 bits can hold
+    let bytes = uncompressed_dds_with_pitch(u32::MAX, 1, 0, u32::MAX, [0; 4], &[]);
+    let expected = (u32::MAX as u64 * u32::MAX as u64).div_ceil(8) as usize;
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().row_pitch(), expected);
+
+    let bytes = four_cc_dds(5, 5, *b"DXT1", &[0; 32]);
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().row_pitch(), 16);
+
+    // The encoder writes the pitch of the image it encodes
+    let mut bytes = Vec::new();
+    Dds::encode_uncompressed(&mut bytes, &RgbaImage::new(7, 3)).unwrap();
+    let raw_header = RawHeader::decode(bytes.as_slice()).unwrap();
+    assert_eq!(raw_header.size, 124);
+    assert_eq!(raw_header.flags, 0x100F);
+    assert_eq!(raw_header.pitch_or_linear_size, 28);
+  }
+
+  #[test]
+  fn test_transcode_uncompressed() {
+    use dds::PixelFormat;
+
+    let data = [0x10, 0x20, 0x30, 0x80, 0xFF, 0xEE, 0xDD, 0x00];
+    let bytes = uncompressed_dds(2, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, PixelFormat::A8R8G8B8);
+
+    let opaque = dds.transcode_uncompressed(PixelFormat::X8R8G8B8).unwrap();
+    assert_eq!(opaque.header.pixel_format, PixelFormat::X8R8G8B8);
+    let round_trip = opaque.transcode_uncompressed(PixelFormat::A8R8G8B8).unwrap();
+    assert_eq!(round_trip.header.pixel_format, PixelFormat::A8R8G8B8);
+
+    for transcoded in [&opaque, &round_trip] {
+      for (pixel, original) in transcoded.layers[0].pixels().zip(dds.layers[0].pixels()) {
+        assert_eq!(pixel.0[..3], original.0[..3]);
+        assert_eq!(pixel.0[3], 255);
+      };
+    };
+
+    assert!(matches!(
+      dds.transcode_uncompressed(PixelFormat::Unknown),
+      Err(EncodeError::UnsupportedPixelFormat(PixelFormat::Unknown))
+    ));
+
+    // Volumes only decode their front slices, and arrays need a DX10 header, so neither can be re-packed
+    let volume = Dds::decode(volume_dds().as_slice()).unwrap();
+    assert!(matches!(
+      volume.transcode_uncompressed(PixelFormat::X8R8G8B8),
+      Err(EncodeError::UnsupportedTextureKind(TextureKind::Volume))
+    ));
+
+    let mut array = dx10_dds(4, 4, DxgiFormat::R8G8B8A8_UNORM, &[0; 256]);
+    array[140..144].copy_from_slice(&4u32.to_le_bytes());
+    let array = Dds::decode(array.as_slice()).unwrap();
+    assert_eq!(array.layers.len(), 4);
+    assert!(matches!(
+      array.transcode_uncompressed(PixelFormat::X8R8G8B8),
+      Err(EncodeError::UnsupportedTextureKind(TextureKind::Array))
+    ));
+  }
+
+  #[test]
+  fn test_decode_d3d_format_four_cc() {
+    // D3DFMT_A16B16G16R16F with R = 1.0, G = 0.5, B = 0.0 and A = 1.0
+    let data = [0x00, 0x3C, 0x00, 0x38, 0x00, 0x00, 0x00, 0x3C];
+    let bytes = four_cc_dds(1, 1, 0x71u32.to_le_bytes(), &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 128, 0, 255]));
+
+    // D3DFMT_A16B16G16R16
+    let data = [0xFF, 0xFF, 0x00, 0x80, 0x00, 0x00, 0xFF, 0xFF];
+    let bytes = four_cc_dds(1, 1, 36u32.to_le_bytes(), &data);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 127, 0, 255]));
+
+    // D3DFMT_R32F
+    let bytes = four_cc_dds(1, 1, 114u32.to_le_bytes(), &0.25f32.to_le_bytes());
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([64, 0, 0, 255]));
+  }
+
+  #[test]
+  fn test_layer_offsets() {
+    let bytes = fs::read("./samples/dxt5.dds").unwrap();
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    let offsets = header.layer_offsets().unwrap();
+    assert_eq!(offsets.len(), header.mipmap_count.max(1) as usize);
+
+    let mut offset = 0;
+    for (level, &layer) in offsets.iter().enumerate() {
+      let width = (header.width as usize >> level).max(1);
+      let height = (header.height as usize >> level).max(1);
+      let size = width.div_ceil(4) * height.div_ceil(4) * 16;
+      assert_eq!(layer, (offset, size));
+      offset += size;
+    };
+
+    assert_eq!(header.size() + offset, bytes.len());
+  }
+
+  #[test]
+  fn test_replace_layer() {
+    let original = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    assert!(original.layers.len() > 2);
+
+    let mut dds = original.clone();
+    let (width, height) = dds.layers[1].dimensions();
+    let image = RgbaImage::from_pixel(width, height, Rgba([1, 2, 3, 4]));
+    dds.replace_layer(1, image.clone()).unwrap();
+
+    assert_eq!(dds.layers[1], image);
+    for (level, (layer, original)) in dds.layers.iter().zip(original.layers.iter()).enumerate() {
+      if level != 1 {
+        assert_eq!(layer, original);
+      };
+    };
+
+    assert!(matches!(
+      dds.replace_layer(2, image.clone()),
+      Err(EncodeError::InvalidMipmapDimensions { level: 2, .. })
+    ));
+    assert!(matches!(
+      dds.replace_layer(original.layers.len(), image),
+      Err(EncodeError::MipmapLevelOutOfRange { .. })
+    ));
+  }
+
+  #[test]
+  fn test_decode_color_key() {
+    let options = DecodeOptions { color_key: Some([255, 0, 255]), ..Default::default() };
+
+    // X8R8G8B8 with a magenta background around a red and a near-magenta pixel
+    let data = [0xFF, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0xFE, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+    let bytes = uncompressed_dds(4, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0x0], &data);
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+
+    let alpha = dds.layers[0].pixels().map(|pixel| pixel.0[3]).collect::<Vec<u8>>();
+    assert_eq!(alpha, [0, 255, 255, 0]);
+    assert_eq!(dds.layers[0].get_pixel(0, 0), &Rgba([255, 0, 255, 0]));
+  }
+
+  #[test]
+  fn test_header_summary() {
+    let header = Header::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    assert_eq!(header.summary(), "4x4 DXT5 (BC3) mips=3 alpha");
+
+    let bytes = uncompressed_dds(3, 2, 16, [0xF800, 0x7E0, 0x1F, 0x0], &[0; 12]);
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().summary(), "3x2 R5G6B5 mips=1 opaque");
+
+    let bytes = dx10_dds(1, 1, DxgiFormat::R8G8B8A8_UNORM, &[0; 4]);
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().summary(), "1x1 DX10 (R8G8B8A8_UNORM) mips=1 alpha");
+
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &cubemap_faces(), dds::Compression::None).unwrap();
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().summary(), "4x4 A8B8G8R8 mips=3 cubemap alpha");
+  }
+
+  #[test]
+  fn test_decode_gray() {
+    let bytes = uncompressed_dds(3, 1, 8, [0x0, 0x0, 0x0, 0xFF], &[0x00, 0x80, 0xFF]);
+    let image = Dds::decode_gray(bytes.as_slice(), 0).unwrap();
+    assert_eq!(image.as_raw(), &vec![0x00, 0x80, 0xFF]);
+
+    let bytes = with_raw_header(&uncompressed_dds(2, 2, 8, [0xFF, 0x0, 0x0, 0x0], &[1, 2, 3, 4, 5]), |raw_header| {
+      raw_header.mipmap_count = 2;
+    });
+    assert_eq!(Dds::decode_gray(bytes.as_slice(), 0).unwrap().as_raw(), &vec![1, 2, 3, 4]);
+    assert_eq!(Dds::decode_gray(bytes.as_slice(), 1).unwrap().as_raw(), &vec![5]);
+    assert!(matches!(Dds::decode_gray(bytes.as_slice(), 2), Err(DecodeError::MipmapLevelOutOfRange { .. })));
+
+    // BC4 with endpoints 255 and 0, every pixel using index 1 (the second endpoint) apart from the first
+    let block = [0xFF, 0x00, 0x48, 0x92, 0x24, 0x49, 0x92, 0x24];
+    let bytes = four_cc_dds(4, 4, *b"ATI1", &block);
+    let image = Dds::decode_gray(bytes.as_slice(), 0).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [255]);
+    assert!(image.pixels().skip(1).all(|pixel| pixel.0 == [0]));
+
+    assert!(matches!(Dds::decode_gray(File::open("./samples/dxt1.dds").unwrap(), 0), Err(DecodeError::NotSingleChannel)));
+  }
+
+  #[test]
+  fn test_encode_edge_pad() {
+    let image = RgbaImage::from_fn(5, 5, |x, y| Rgba([200 - x as u8 * 10, 100 + y as u8 * 10, 50, 255]));
+
+    for &compression in &[Compression::DXT1, Compression::DXT3, Compression::DXT5] {
+      for &edge_pad in &[EdgePad::Clamp, EdgePad::Mirror, EdgePad::Zero] {
+        let options = EncodeOptions { edge_pad, ..Default::default() };
+        let mut bytes = Vec::new();
+        Dds::encode_with(&mut bytes, &image, compression, &options).unwrap();
+
+        let dds = Dds::decode(bytes.as_slice()).unwrap();
+        assert_eq!(dds.header.compression, compression);
+        assert_eq!(dds.layers[0].dimensions(), (5, 5));
+
+        // The edge blocks only hold one real row or column, so everything
+        // should be close once the padding is replicated from it
+        if edge_pad == EdgePad::Clamp {
+          for (x, y) in (0..5).map(|i| (4, i)).chain((0..5).map(|i| (i, 4))) {
+            let (source, decoded) = (image.get_pixel(x, y).0, dds.layers[0].get_pixel(x, y).0);
+            for c in 0..4 {
+              assert!((source[c] as i32 - decoded[c] as i32).abs() <= 8, "{:?} {:?} at {:?}", source, decoded, (x, y));
+            };
+          };
+        };
+      };
+    };
+  }
+
+  #[test]
+  fn test_tool_signature() {
+    let bytes = with_raw_header(&four_cc_dds(4, 4, *b"DXT1", &[0; 8]), |raw_header| {
+      raw_header.reserved[9] = u32::from_le_bytes(*b"NVTT");
+      raw_header.reserved[10] = 0x20101;
+    });
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.tool_signature(), Some(*b"NVTT"));
+
+    let header = Header::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert_eq!(header.tool_signature(), None);
+
+    let mut reserved = [0; 11];
+    reserved[9] = u32::from_le_bytes(*b"GIMP");
+    let header = Header { reserved, ..header };
+    assert_eq!(header.tool_signature(), None);
+  }
+
+  #[test]
+  fn test_decode_dxt1_punch_through() {
+    // color0 (black) <= color1 (white) selects the three color mode, with each row using one index
+    let block = [0x00, 0x00, 0xFF, 0xFF, 0x00, 0x55, 0xAA, 0xFF];
+    let expected = [[0, 0, 0, 255], [255, 255, 255, 255], [127, 127, 127, 255], [0, 0, 0, 0]];
+
+    let image = &Dds::decode(four_cc_dds(4, 4, *b"DX1A", &block).as_slice()).unwrap().layers[0];
+    for (y, &pixel) in expected.iter().enumerate() {
+      assert!((0..4).all(|x| image.get_pixel(x, y as u32).0 == pixel), "index {}", y);
+    };
+
+    // Without alpha, index 3 is opaque black instead
+    let image = &Dds::decode(four_cc_dds(4, 4, *b"DXT1", &block).as_slice()).unwrap().layers[0];
+    assert!(image.pixels().all(|pixel| pixel.0[3] == 255));
+    assert_eq!(image.get_pixel(0, 3).0, [0, 0, 0, 255]);
+
+    // Equal endpoints are also the three color mode, index 2 must stay opaque
+    let block = [0x00, 0x00, 0x00, 0x00, 0xAA, 0xAA, 0xFF, 0xFF];
+    let image = &Dds::decode(four_cc_dds(4, 4, *b"DX1A", &block).as_slice()).unwrap().layers[0];
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    assert_eq!(image.get_pixel(0, 3).0, [0, 0, 0, 0]);
+  }
+
+  #[test]
+  fn test_decode_dynamic() {
+    let bytes = uncompressed_dds(2, 1, 8, [0x0, 0x0, 0x0, 0xFF], &[0x10, 0x20]);
+    match &Dds::decode_dynamic(bytes.as_slice()).unwrap()[..] {
+      [DynamicImage::ImageLuma8(image)] => assert_eq!(image.as_raw(), &vec![0x10, 0x20]),
+      images => panic!("expected a single Luma8 image, found {:?}", images)
+    };
+
+    let bytes = uncompressed_dds(1, 1, 8, [0xFF, 0x0, 0x0, 0x0], &[0x30]);
+    assert!(matches!(&Dds::decode_dynamic(bytes.as_slice()).unwrap()[..], [DynamicImage::ImageLuma8(_)]));
+
+    let bytes = with_raw_header(&uncompressed_dds(1, 1, 16, [0xFF, 0x0, 0x0, 0xFF00], &[0x40, 0x80]), |raw_header| {
+      raw_header.pixel_format.flags = 0x20001;
+    });
+    match &Dds::decode_dynamic(bytes.as_slice()).unwrap()[..] {
+      [DynamicImage::ImageLumaA8(image)] => assert_eq!(image.as_raw(), &vec![0x40, 0x80]),
+      images => panic!("expected a single LumaA8 image, found {:?}", images)
+    };
+
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0x0], &[0x03, 0x02, 0x01, 0x00]);
+    match &Dds::decode_dynamic(bytes.as_slice()).unwrap()[..] {
+      [DynamicImage::ImageRgb8(image)] => assert_eq!(image.as_raw(), &vec![0x01, 0x02, 0x03]),
+      images => panic!("expected a single Rgb8 image, found {:?}", images)
+    };
+
+    let images = Dds::decode_dynamic(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    assert_eq!(images.len(), 3);
+    assert!(images.iter().all(|image| matches!(image, DynamicImage::ImageRgba8(_))));
+  }
+
+  #[test]
+  fn test_decode_overlarge_mipmap_count() {
+    // A full chain for 64x64 is 7 levels, down to 1x1
+    let data = vec![0x7F; (0..7).map(|level| 4 * (64 >> level) * (64 >> level)).sum()];
+    let bytes = with_raw_header(&uncompressed_dds(64, 64, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+      raw_header.flags |= 0x20000;
+      raw_header.mipmap_count = 100;
+    });
+
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert_eq!(header.layer_offsets().unwrap().len(), 7);
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers.len(), 7);
+    assert_eq!(dds.layers[6].dimensions(), (1, 1));
+
+    let options = DecodeOptions { strict: true, ..Default::default() };
+    assert!(matches!(
+      Dds::decode_with(bytes.as_slice(), &options),
+      Err(DecodeError::InvalidMipmapCount { count: 100, max: 7 })
+    ));
+  }
+
+  #[test]
+  fn test_array_to_sheet() {
+    // Four 2x1 slices with a single mipmap level, each a different shade of red
+    let data = (0..4u8).flat_map(|slice| vec![slice * 50, 0, 0, 255, slice * 50, 0, 0, 255]).collect::<Vec<u8>>();
+    let mut bytes = dx10_dds(2, 1, DxgiFormat::R8G8B8A8_UNORM, &data);
+    bytes[140..144].copy_from_slice(&4u32.to_le_bytes());
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.array_size(), 4);
+    assert_eq!(dds.layers.len(), 4);
+
+    let sheet = dds.array_to_sheet(2, 0);
+    assert_eq!(sheet.dimensions(), (4, 2));
+    for (slice, (x, y)) in [(0, 0), (2, 0), (0, 1), (2, 1)].iter().enumerate() {
+      assert_eq!(sheet.get_pixel(*x, *y).0, [slice as u8 * 50, 0, 0, 255]);
+      assert_eq!(sheet.get_pixel(x + 1, *y).0, [slice as u8 * 50, 0, 0, 255]);
+    };
+
+    assert_eq!(dds.array_to_sheet(3, 0).dimensions(), (6, 2));
+    assert!(std::panic::catch_unwind(|| dds.array_to_sheet(0, 0)).is_err());
+  }
+
+  #[test]
+  fn test_encode_format_dither() {
+    let image = RgbaImage::from_fn(256, 4, |x, _| Rgba([x as u8, x as u8, x as u8, 255]));
+
+    // Banding shows up as local averages drifting away from the smooth source, by up to half
+    // of a quantization step, which dithering should spread out across each 4x4 neighbourhood
+    let banding = |dither: bool| {
+      let mut bytes = Vec::new();
+      let options = EncodeOptions { dither, ..Default::default() };
+      Dds::encode_format(&mut bytes, &image, PixelFormat::R5G6B5, &options).unwrap();
+
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.header.pixel_format, PixelFormat::R5G6B5);
+      (0..64).map(|block| {
+        let (source, decoded) = (0..16).fold((0.0, 0.0), |(source, decoded), i| {
+          let (x, y) = (block * 4 + i % 4, i / 4);
+          (source + image.get_pixel(x, y).0[0] as f64, decoded + dds.layers[0].get_pixel(x, y).0[0] as f64)
+        });
+        ((source - decoded) / 16.0).abs()
+      }).fold(0.0, f64::max)
+    };
+
+    assert!(banding(true) < banding(false), "{} >= {}", banding(true), banding(false));
+
+    let mut bytes = Vec::new();
+    assert!(matches!(
+      Dds::encode_format(&mut bytes, &image, PixelFormat::L8, &EncodeOptions::default()),
+      Err(EncodeError::UnsupportedPixelFormat(PixelFormat::L8))
+    ));
+  }
+
+  #[test]
+  fn test_decode_uncompressed_borrowed() {
+    let data = (0..20).collect::<Vec<u8>>();
+    let bytes = with_raw_header(&uncompressed_dds(2, 2, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+      raw_header.flags |= 0x20000;
+      raw_header.mipmap_count = 2;
+    });
+
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    let payload = &bytes[header.size()..];
+    let layers = Dds::decode_uncompressed_borrowed(&header, payload).unwrap();
+    assert_eq!(layers.iter().map(|layer| layer.len()).collect::<Vec<_>>(), vec![16, 4]);
+    assert!(std::ptr::eq(layers[0].as_ptr(), payload.as_ptr()));
+    assert!(std::ptr::eq(layers[1].as_ptr(), payload[16..].as_ptr()));
+    assert_eq!(layers[1], &data[16..]);
+
+    assert!(matches!(Dds::decode_uncompressed_borrowed(&header, &payload[..18]), Err(DecodeError::Io(_))));
+
+    let bytes = uncompressed_dds(2, 2, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &data[..16]);
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    assert!(matches!(
+      Dds::decode_uncompressed_borrowed(&header, &bytes[header.size()..]),
+      Err(DecodeError::UnsupportedPixelFormat(PixelFormat::A8B8G8R8))
+    ));
+  }
+
+  #[test]
+  fn test_encode_repaired() {
+    // Missing the pitch and mipmap count flags and the texture caps, with a bad
+    // pixel format size and more mipmaps than the texture has room for
+    let data = (0..84).map(|i| i as u8 * 3).collect::<Vec<u8>>();
+    let bytes = with_raw_header(&uncompressed_dds(4, 4, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+      raw_header.flags = 0x1007;
+      raw_header.pitch_or_linear_size = 0;
+      raw_header.mipmap_count = 9;
+      raw_header.caps = 0;
+      raw_header.pixel_format.size = 24;
+    });
+    assert!(RawHeader::decode(bytes.as_slice()).unwrap().validate(true).is_err());
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    let mut repaired = Vec::new();
+    dds.encode_repaired(&mut repaired).unwrap();
+
+    let raw_header = RawHeader::decode(repaired.as_slice()).unwrap();
+    assert!(raw_header.validate(true).unwrap().is_empty());
+    assert_eq!(raw_header.flags, 0x2100F);
+    assert_eq!(raw_header.pitch_or_linear_size, 16);
+    assert_eq!(raw_header.mipmap_count, 3);
+    assert_eq!(raw_header.caps, 0x401008);
+
+    let options = DecodeOptions { strict: true, ..Default::default() };
+    assert_eq!(Dds::decode_with(repaired.as_slice(), &options).unwrap().layers, dds.layers);
+
+    // Compressed files give a linear size instead
+    let dds = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    let mut repaired = Vec::new();
+    dds.encode_repaired(&mut repaired).unwrap();
+
+    let raw_header = RawHeader::decode(repaired.as_slice()).unwrap();
+    assert_eq!(raw_header.flags & 0x80008, 0x80000);
+    assert_eq!(raw_header.pitch_or_linear_size, 16);
+    assert_eq!(Dds::decode(repaired.as_slice()).unwrap().layers.len(), 3);
+  }
+
+  #[test]
+  fn test_decode_sub_block_textures() {
+    // Every texel of this DXT3 block has its own alpha, and the colors differ by row
+    let block = [0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x55, 0xAA, 0xFF];
+    let reference = Dds::decode(four_cc_dds(4, 4, *b"DXT3", &block).as_slice()).unwrap().layers.remove(0);
+    assert_eq!(reference.get_pixel(1, 0).0[3], 17);
+    assert_eq!(reference.get_pixel(0, 1).0[3], 4 * 17);
+
+    for size in 1..4 {
+      let image = Dds::decode(four_cc_dds(size, size, *b"DXT3", &block).as_slice()).unwrap().layers.remove(0);
+      assert_eq!(image.dimensions(), (size, size));
+      for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel, reference.get_pixel(x, y), "{}x{} texture at {:?}", size, size, (x, y));
+      };
+    };
+
+    // The same goes for a 4x4 mipmap chain, whose last two levels each take up one block
+    let bytes = with_raw_header(&four_cc_dds(4, 4, *b"DXT3", &block.repeat(3)), |raw_header| {
+      raw_header.flags |= 0x20000;
+      raw_header.mipmap_count = 3;
+    });
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[1].get_pixel(1, 1), reference.get_pixel(1, 1));
+    assert_eq!(dds.layers[2].get_pixel(0, 0), reference.get_pixel(0, 0));
+  }
+
+  #[test]
+  fn test_encode_alpha_threshold() {
+    let image = RgbaImage::from_fn(16, 4, |x, _| Rgba([200, 120, 40, x as u8 * 17]));
+
+    for &alpha_threshold in &[128, 50, 255] {
+      let mut bytes = Vec::new();
+      let options = EncodeOptions { alpha_threshold, ..Default::default() };
+      Dds::encode_with(&mut bytes, &image, Compression::DXT1A, &options).unwrap();
+
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      for (x, y, pixel) in dds.layers[0].enumerate_pixels() {
+        match image.get_pixel(x, y).0[3] < alpha_threshold {
+          true => assert_eq!(pixel.0[3], 0, "{} at {:?}", alpha_threshold, (x, y)),
+          false => {
+            assert_eq!(pixel.0[3], 255, "{} at {:?}", alpha_threshold, (x, y));
+            assert!(pixel.0.iter().zip([200u8, 120, 40].iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 8));
+          }
+        };
+      };
+    };
+  }
+
+  #[test]
+  fn test_is_opaque() {
+    let dds = Dds::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert!(dds.is_opaque());
+
+    let bytes = uncompressed_dds(2, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &[0, 0, 0, 255, 0, 0, 0, 254]);
+    assert!(!Dds::decode(bytes.as_slice()).unwrap().is_opaque());
+
+    // Only the main image counts
+    let mut dds = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    dds.layers[0].pixels_mut().for_each(|pixel| pixel.0[3] = 255);
+    dds.layers[1].pixels_mut().for_each(|pixel| pixel.0[3] = 0);
+    assert!(dds.is_opaque());
+  }
+
+  #[test]
+  fn test_cubemap_into_faces() {
+    let faces = cubemap_faces();
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &faces, Compression::None).unwrap();
+
+    let split = Cubemap::decode(bytes.as_slice()).unwrap().into_faces().unwrap();
+    for (dds, face) in split.iter().zip(faces.iter()) {
+      assert!(!dds.header.is_cubemap());
+      assert_eq!((dds.header.width, dds.header.height), (4, 4));
+      assert_eq!(dds.header.mipmap_count, 3);
+      assert_eq!(&dds.layers, face);
+
+      // Each face round trips as a texture of its own
+      let mut face_bytes = Vec::new();
+      dds.header.to_raw_header().encode(&mut face_bytes).unwrap();
+      face.iter().for_each(|image| face_bytes.extend_from_slice(image.as_raw()));
+      assert_eq!(&Dds::decode(face_bytes.as_slice()).unwrap().layers, face);
+    };
+
+    let dds = Dds::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    let cubemap = Cubemap { header: dds.header, faces: Default::default() };
+    assert!(matches!(cubemap.into_faces(), Err(DecodeError::NotCubemap)));
+  }
+
+  #[test]
+  fn test_decode_volume() {
+    let bytes = volume_dds();
+    let volume = Volume::decode(bytes.as_slice()).unwrap();
+    assert!(volume.header.is_volume());
+    assert_eq!((volume.header.depth, volume.header.level_depth(3)), (8, 1));
+    assert_eq!(volume.levels.iter().map(|slices| slices.len()).collect::<Vec<_>>(), vec![8, 4, 2, 1]);
+
+    let mut slice = 0;
+    for (level, slices) in volume.levels.iter().enumerate() {
+      for image in slices.iter() {
+        assert_eq!(image.dimensions(), (8 >> level, 8 >> level));
+        assert!(image.pixels().all(|pixel| pixel.0 == [slice, slice, slice, 255]));
+        slice += 1;
+      };
+    };
+
+    let raw_header = volume.header.to_raw_header();
+    assert_eq!((raw_header.depth, raw_header.flags & 0x800000), (8, 0x800000));
+
+    let options = DecodeOptions { max_level: Some(1), ..Default::default() };
+    assert_eq!(Volume::decode_with(bytes.as_slice(), &options).unwrap().levels.len(), 2);
+    assert!(matches!(Volume::decode(File::open("./samples/dxt1.dds").unwrap()), Err(DecodeError::NotVolume)));
+  }
+
+  #[test]
+  fn test_decode_unpremultiply() {
+    let straight = [[200, 100, 50, 255], [200, 100, 50, 128], [30, 240, 90, 64], [10, 20, 30, 0]];
+    let data = straight.iter().flatten().copied().collect::<Vec<u8>>();
+    let bytes = uncompressed_dds(4, 1, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], &data);
+
+    let options = DecodeOptions { premultiply: true, ..Default::default() };
+    let premultiplied = Dds::decode_with(bytes.as_slice(), &options).unwrap().layers.remove(0);
+    assert_eq!(premultiplied.get_pixel(1, 0).0, [100, 50, 25, 128]);
+
+    let bytes = uncompressed_dds(4, 1, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], premultiplied.as_raw());
+    let options = DecodeOptions { unpremultiply: true, ..Default::default() };
+    let recovered = Dds::decode_with(bytes.as_slice(), &options).unwrap().layers.remove(0);
+    for (pixel, original) in recovered.pixels().zip(straight.iter()).take(3) {
+      assert_eq!(pixel.0[3], original[3]);
+      // Premultiplying rounds away some precision, more so the lower the alpha
+      assert!(pixel.0.iter().zip(original.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 2), "{:?} {:?}", pixel, original);
+    };
+
+    // Transparent pixels have nothing to recover
+    assert_eq!(recovered.get_pixel(3, 0).0, [0, 0, 0, 0]);
+  }
+
+  #[test]
+  fn test_decode_verbose() {
+    let data = vec![0x40; 4 * (16 + 4 + 1)];
+    let bytes = with_raw_header(&uncompressed_dds(4, 4, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+      raw_header.flags = 0x2000E;
+      raw_header.mipmap_count = 10;
+      raw_header.pixel_format.size = 0;
+    });
+
+    let (dds, diagnostics) = Dds::decode_verbose(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers.len(), 3);
+    assert_eq!(diagnostics, vec![
+      Diagnostic::InvalidPixelFormatSize(0),
+      Diagnostic::InvalidMipmapCount { count: 10, max: 3 },
+      Diagnostic::MissingRequiredFlags(0x1001)
+    ]);
+
+    let options = DecodeOptions { strict: true, ..Default::default() };
+    assert!(matches!(Dds::decode_with(bytes.as_slice(), &options), Err(DecodeError::InvalidPixelFormatSize(0))));
+
+    let (_, diagnostics) = Dds::decode_verbose(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    assert_eq!(diagnostics, vec![]);
+
+    // A mipmap chain without a count runs all the way down to 1x1, which strict decoding accepts
+    let bytes = with_raw_header(&uncompressed_dds(4, 4, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &data), |raw_header| {
+      raw_header.caps |= 0x400008;
+      raw_header.mipmap_count = 0;
+    });
+    let (dds, diagnostics) = Dds::decode_verbose(bytes.as_slice()).unwrap();
+    assert_eq!((dds.layers.len(), diagnostics), (3, vec![Diagnostic::ImpliedMipmapCount(3)]));
+    assert_eq!(Dds::decode_with(bytes.as_slice(), &options).unwrap().layers.len(), 3);
+
+    // Transparent DXT1 texels come out opaque black when nothing marks the file as having alpha
+    let bytes = four_cc_dds(4, 4, *b"DXT1", &[0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    let (dds, diagnostics) = Dds::decode_verbose(bytes.as_slice()).unwrap();
+    assert_eq!(diagnostics, vec![Diagnostic::OpaquePunchThroughAlpha]);
+    assert!(dds.layers[0].pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+    assert!(Dds::decode_with(bytes.as_slice(), &options).is_ok());
+
+    // Index 3 of the four color mode is just another color
+    let bytes = four_cc_dds(4, 4, *b"DXT1", &[0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(Dds::decode_verbose(bytes.as_slice()).unwrap().1, vec![]);
+  }
+
+  #[test]
+  fn test_decode_a16b16g16r16() {
+    let pixel = [0x1234u16, 0x8080, 0xFFFF, 0x4000];
+    let data = pixel.iter().flat_map(|channel| channel.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+
+    for bytes in [four_cc_dds(1, 1, [36, 0, 0, 0], &data), dx10_dds(1, 1, DxgiFormat::R16G16B16A16_UNORM, &data)].iter() {
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.layers[0].get_pixel(0, 0).0, [18, 128, 255, 63]);
+
+      let layers = Dds::decode_u16(bytes.as_slice()).unwrap();
+      assert_eq!(layers[0].get_pixel(0, 0).0, pixel);
+    };
+
+    // 8-bit formats are scaled up to the full range
+    let layers = Dds::decode_u16(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert_eq!(layers[0].get_pixel(0, 0).0[3], 65535);
+  }
+
+  #[test]
+  fn test_decode_dxt5_alpha() {
+    let dds = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap();
+    for (level, layer) in dds.layers.iter().enumerate() {
+      let alpha = Dds::decode_dxt5_alpha(File::open("./samples/dxt5.dds").unwrap(), level).unwrap();
+      assert_eq!(alpha.dimensions(), layer.dimensions());
+      assert!(alpha.pixels().zip(layer.pixels()).all(|(a, pixel)| a.0[0] == pixel.0[3]));
+    };
+
+    // A 5x3 image spans partial blocks, each texel with its own alpha
+    let image = RgbaImage::from_fn(5, 3, |x, y| Rgba([x as u8 * 40, 90, 200, (x + y * 5) as u8 * 16]));
+    let mut bytes = Vec::new();
+    Dds::encode(&mut bytes, &image, Compression::DXT5).unwrap();
+    let full = Dds::decode(bytes.as_slice()).unwrap().layers.remove(0);
+    let alpha = Dds::decode_dxt5_alpha(bytes.as_slice(), 0).unwrap();
+    assert!(alpha.enumerate_pixels().all(|(x, y, a)| a.0[0] == full.get_pixel(x, y).0[3]));
+
+    assert!(matches!(Dds::decode_dxt5_alpha(bytes.as_slice(), 1), Err(DecodeError::MipmapLevelOutOfRange { level: 1, count: 1 })));
+    assert!(matches!(
+      Dds::decode_dxt5_alpha(File::open("./samples/dxt1.dds").unwrap(), 0),
+      Err(DecodeError::UnsupportedCompression(Compression::DXT1))
+    ));
+  }
+
+  #[test]
+  fn test_decode_thumbnail() {
+    let layers = Dds::decode(File::open("./samples/dxt5.dds").unwrap()).unwrap().layers;
+    let thumbnail = Dds::decode_thumbnail(File::open("./samples/dxt5.dds").unwrap(), 2).unwrap();
+    assert_eq!(&thumbnail, &layers[1]);
+    let thumbnail = Dds::decode_thumbnail(File::open("./samples/dxt5.dds").unwrap(), 3).unwrap();
+    assert_eq!(thumbnail.dimensions(), (3, 3));
+
+    // A rectangular image without mipmaps keeps its aspect ratio
+    let image = RgbaImage::from_fn(64, 16, |x, y| Rgba([x as u8 * 4, y as u8 * 16, 0, 255]));
+    let mut bytes = Vec::new();
+    Dds::encode(&mut bytes, &image, Compression::None).unwrap();
+    for &max_dim in &[1, 10, 16, 64, 100] {
+      let thumbnail = Dds::decode_thumbnail(bytes.as_slice(), max_dim).unwrap();
+      let (width, height) = thumbnail.dimensions();
+      assert!(width <= max_dim.max(1) && height <= max_dim.max(1), "{:?} for {}", (width, height), max_dim);
+      assert_eq!(width, max_dim.min(64));
+    };
+  }
+
+  #[test]
+  fn test_is_dds() {
+    assert!(dds::is_dds(&std::fs::read("./samples/dxt1.dds").unwrap()));
+    assert!(dds::is_dds(b"DDS "));
+    assert!(!dds::is_dds(b"DDS"));
+    assert!(!dds::is_dds(b"dds \x7C\0\0\0"));
+    assert!(!dds::is_dds(b"\x89PNG\r\n"));
+    assert!(!dds::is_dds(&[]));
+  }
+
+  #[test]
+  fn test_decode_pad_truncated() {
+    let bytes = std::fs::read("./samples/dxt5.dds").unwrap();
+    let truncated = &bytes[..bytes.len() - 3];
+    assert!(matches!(Dds::decode(truncated), Err(DecodeError::InvalidBlockLength { expected: 16, found: 13 })));
+
+    let options = DecodeOptions { pad_truncated: true, ..Default::default() };
+    let dds = Dds::decode_with(truncated, &options).unwrap();
+    let full = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers.len(), 3);
+    assert_eq!(dds.layers[..2], full.layers[..2]);
+
+    // Missing blocks are black, with DXT5 alpha blocks of zero being transparent
+    let bytes = four_cc_dds(8, 4, *b"DXT5", &std::fs::read("./samples/dxt5.dds").unwrap()[128..144]);
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert!((4..8).all(|x| dds.layers[0].get_pixel(x, 0).0 == [0, 0, 0, 0]));
+  }
+
+  #[test]
+  fn test_encode_to_vec() {
+    let image = RgbaImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 128, 255]));
+    let bytes = dds::encode_to_vec(&image, Compression::None).unwrap();
+    assert!(dds::is_dds(&bytes));
+    assert_eq!(Dds::decode(bytes.as_slice()).unwrap().layers, vec![image.clone()]);
+
+    let bytes = dds::encode_to_vec(&image, Compression::DXT1).unwrap();
+    assert_eq!(bytes.len(), 128 + 2 * 8);
+    assert_eq!(Dds::decode(bytes.as_slice()).unwrap().layers[0].dimensions(), (8, 4));
+
+    assert!(matches!(dds::encode_to_vec(&image, Compression::DX10), Err(EncodeError::UnsupportedCompression(Compression::DX10))));
+  }
+
+  #[test]
+  fn test_encode_complex_caps() {
+    const COMPLEX: u32 = 0x8;
+    const MIPMAP: u32 = 0x400000;
+    let caps = |bytes: &[u8]| RawHeader::decode(bytes).unwrap().caps & (COMPLEX | MIPMAP | 0x1000);
+
+    let image = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+    for &compression in &[Compression::None, Compression::DXT1, Compression::DXT5] {
+      assert_eq!(caps(&dds::encode_to_vec(&image, compression).unwrap()), 0x1000);
+    };
+
+    let mut bytes = Vec::new();
+    Dds::encode_format(&mut bytes, &image, PixelFormat::R5G6B5, &EncodeOptions::default()).unwrap();
+    assert_eq!(caps(&bytes), 0x1000);
+
+    let levels = [image.clone(), RgbaImage::new(2, 2), RgbaImage::new(1, 1)];
+    for &compression in &[Compression::None, Compression::DXT3] {
+      let mut bytes = Vec::new();
+      Dds::encode_with_mipmaps(&mut bytes, &levels, compression, &EncodeOptions::default()).unwrap();
+      assert_eq!(caps(&bytes), COMPLEX | MIPMAP | 0x1000);
+
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.layers.len(), 3);
+      assert_eq!(dds.header.to_raw_header().caps & COMPLEX, COMPLEX);
+    };
+
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &cubemap_faces(), Compression::None).unwrap();
+    assert_eq!(caps(&bytes), COMPLEX | MIPMAP | 0x1000);
+
+    // Stale caps from a decoded single image don't carry over
+    let header = Header::decode(dds::encode_to_vec(&image, Compression::None).unwrap().as_slice()).unwrap();
+    assert_eq!(Header { caps: COMPLEX | MIPMAP, ..header.clone() }.to_raw_header().caps, 0x1000);
+    assert_eq!(Header { caps2: 0x200000, depth: 4, ..header }.to_raw_header().caps, COMPLEX | 0x1000);
+
+    let mut bytes = Vec::new();
+    assert!(matches!(
+      Dds::encode_with_mipmaps(&mut bytes, &levels[..2], Compression::None, &EncodeOptions::default()),
+      Err(EncodeError::InvalidMipmapCount { expected: 3, found: 2 })
+    ));
+    assert!(matches!(
+      Dds::encode_with_mipmaps(&mut bytes, &[image.clone(), RgbaImage::new(2, 1), RgbaImage::new(1, 1)], Compression::None, &EncodeOptions::default()),
+      Err(EncodeError::InvalidMipmapDimensions { level: 1, expected: (2, 2), found: (2, 1) })
+    ));
+  }
+
+  #[test]
+  fn test_decode_2bit_alpha() {
+    // Full red, half green and no blue, with each pixel using a different alpha
+    let data = (0..4u32).flat_map(|alpha| (alpha << 30 | 0x3FF << 20 | 0x200 << 10).to_le_bytes().to_vec()).collect::<Vec<u8>>();
+    let bytes = uncompressed_dds(4, 1, 32, [0x3FF00000, 0xFFC00, 0x3FF, 0xC0000000], &data);
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, PixelFormat::A2R10G10B10);
+    let pixels = dds.layers[0].pixels().map(|pixel| pixel.0).collect::<Vec<_>>();
+    assert_eq!(pixels, vec![[255, 127, 0, 0], [255, 127, 0, 85], [255, 127, 0, 170], [255, 127, 0, 255]]);
+
+    let options = DecodeOptions { opaque_2bit_alpha: true, ..Default::default() };
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert!(dds.layers[0].pixels().all(|pixel| pixel.0 == [255, 127, 0, 255]));
+
+    // Wider alpha channels are left alone
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &[0, 0, 0, 0x40]);
+    assert_eq!(Dds::decode_with(bytes.as_slice(), &options).unwrap().layers[0].get_pixel(0, 0).0[3], 0x40);
+  }
+
+  #[test]
+  fn test_decode_as() {
+    // An 8-bit format decodes into `u8` exactly as `Dds::decode` does
+    let dds = Dds::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    let layers = Dds::decode_as::<Rgba<u8>, _>(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert_eq!(layers, dds.layers);
+
+    // A 16-bit format keeps its full precision as `u16`, and is rounded as `u8`
+    let pixel = [0x1234u16, 0x8080, 0xFFFF, 0x0000];
+    let data = pixel.iter().flat_map(|channel| channel.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+    let bytes = dx10_dds(1, 1, DxgiFormat::R16G16B16A16_UNORM, &data);
+    let layers = Dds::decode_as::<Rgba<u16>, _>(bytes.as_slice()).unwrap();
+    assert_eq!(layers[0].get_pixel(0, 0).0, pixel);
+    let layers = Dds::decode_as::<Rgba<u8>, _>(bytes.as_slice()).unwrap();
+    assert_eq!(layers[0].get_pixel(0, 0).0, [18, 128, 255, 0]);
+
+    // Options still apply to the `u8` path
+    let options = DecodeOptions { max_level: Some(0), ..Default::default() };
+    let layers = Dds::decode_as_with::<Rgba<u16>, _>(File::open("./samples/dxt1.dds").unwrap(), &options).unwrap();
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers[0].get_pixel(0, 0).0[3], 65535);
+  }
+
+  #[test]
+  fn test_decode_verify_blocks() {
+    // Three of the four blocks of an 8x8 DXT1 texture have been zeroed out
+    let mut data = vec![0u8; 32];
+    data[..8].copy_from_slice(&[0xE0, 0x07, 0x1F, 0x00, 0x1B, 0x6C, 0xB1, 0xC6]);
+    let bytes = four_cc_dds(8, 8, *b"DXT1", &data);
+
+    // Decodes as normal unless asked to check
+    assert!(Dds::decode(bytes.as_slice()).is_ok());
+
+    let options = DecodeOptions { verify_blocks: Some(50), ..Default::default() };
+    match Dds::decode_with(bytes.as_slice(), &options) {
+      Err(DecodeError::LikelyCorrupt { suspicious: 3, total: 4 }) => (),
+      result => panic!("expected a corrupt file, got {:?}", result.map(|dds| dds.header))
+    };
+
+    let options = DecodeOptions { verify_blocks: Some(75), ..Default::default() };
+    assert!(Dds::decode_with(bytes.as_slice(), &options).is_ok());
+  }
+
+  #[test]
+  fn test_decode_yuv422() {
+    // A single macropixel covers two pixels, sharing the same chroma
+    let pixels = [[255, 0, 0, 255], [128, 128, 128, 255]];
+    for (four_cc, data) in [(*b"YUY2", [81, 90, 126, 240]), (*b"UYVY", [90, 81, 240, 126])].iter() {
+      let dds = Dds::decode(four_cc_dds(2, 1, *four_cc, data).as_slice()).unwrap();
+      assert_eq!(dds.layers[0].get_pixel(0, 0).0, pixels[0]);
+      assert_eq!(dds.layers[0].get_pixel(1, 0).0[3], 255);
+    };
+
+    // Pure gray has no chroma, and an odd width drops the padding pixel
+    let data = [126, 128, 0, 128, 126, 128, 0, 128];
+    let dds = Dds::decode(four_cc_dds(1, 2, *b"YUY2", &data).as_slice()).unwrap();
+    assert_eq!(dds.layers[0].dimensions(), (1, 2));
+    assert!(dds.layers[0].pixels().all(|pixel| pixel.0 == pixels[1]));
+
+    // A payload cut short fails rather than decoding missing rows
+    let bytes = four_cc_dds(2, 2, *b"UYVY", &data[..6]);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::InvalidLayerLength { expected: 8, found: 6 })));
+  }
+
+  #[test]
+  fn test_describe() {
+    // A cubemap with a full 4x4 mipmap chain on each face
+    let faces = cubemap_faces();
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &faces, Compression::None).unwrap();
+
+    let layout = Dds::describe(bytes.as_slice()).unwrap();
+    assert_eq!(layout.header_size, 128);
+    assert!(!layout.has_dx10);
+    assert_eq!((layout.mipmap_count, layout.face_count, layout.array_size, layout.depth), (3, 6, 1, 1));
+    assert_eq!(layout.surfaces.len(), 18);
+
+    let face_size = 4 * (16 + 4 + 1);
+    assert_eq!(layout.surfaces[4], SurfaceLayout { texture: 1, level: 1, slice: 0, width: 2, height: 2, offset: face_size + 64, size: 16 });
+    let last = layout.surfaces.last().unwrap();
+    assert_eq!(last.offset + last.size, bytes.len() - layout.header_size);
+
+    // Matches the layers the decoder produces
+    let cubemap = Cubemap::decode(bytes.as_slice()).unwrap();
+    let dimensions = cubemap.faces.iter().flatten().map(|layer| layer.dimensions()).collect::<Vec<_>>();
+    assert_eq!(layout.surfaces.iter().map(|surface| (surface.width, surface.height)).collect::<Vec<_>>(), dimensions);
+  }
+
+  #[test]
+  fn test_save_png() {
+    let path = std::env::temp_dir().join(format!("dds-test-save-png-{}.png", std::process::id()));
+
+    // Colors that aren't premultiplied are written out unchanged
+    let dds = Dds::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    dds.save_png(&path, 0).unwrap();
+    assert_eq!(image::open(&path).unwrap().into_rgba8(), image::open("./samples/dxt1.png").unwrap().into_rgba8());
+
+    // DXT2 stores premultiplied colors, which are divided by alpha on the way out
+    let dds = Dds::decode(File::open("./samples/qt/DXT2.dds").unwrap()).unwrap();
+    assert!(dds.header.is_premultiplied());
+    dds.save_png(&path, 0).unwrap();
+    let options = DecodeOptions { unpremultiply: true, ..Default::default() };
+    let expected = Dds::decode_with(File::open("./samples/qt/DXT2.dds").unwrap(), &options).unwrap();
+    assert_eq!(image::open(&path).unwrap().into_rgba8(), expected.layers[0]);
+
+    // As do DX10 files with the premultiplied alpha mode
+    let mut bytes = dx10_dds(1, 1, DxgiFormat::R8G8B8A8_UNORM, &[64, 32, 0, 128]);
+    bytes[144..148].copy_from_slice(&2u32.to_le_bytes());
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    dds.save_png(&path, 0).unwrap();
+    assert_eq!(image::open(&path).unwrap().into_rgba8().get_pixel(0, 0).0, [128, 64, 0, 128]);
+
+    assert!(matches!(dds.save_png(&path, 1), Err(EncodeError::MipmapLevelOutOfRange { level: 1, count: 1 })));
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn test_decode_swizzle() {
+    // A normal map with X in alpha and Y in green, as in DXT5nm
+    let masks = [0xFF, 0xFF00, 0xFF0000, 0xFF000000];
+    let bytes = uncompressed_dds(2, 1, 32, masks, &[255, 40, 255, 200, 255, 90, 255, 10]);
+
+    let swizzle = [Channel::Alpha, Channel::Green, Channel::Zero, Channel::One];
+    let options = DecodeOptions { swizzle, ..Default::default() };
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0).0, [200, 40, 0, 255]);
+    assert_eq!(dds.layers[0].get_pixel(1, 0).0, [10, 90, 0, 255]);
+
+    // The identity swizzle leaves the pixels alone
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(DecodeOptions::default().swizzle, Channel::IDENTITY);
+    assert_eq!(dds.layers[0].get_pixel(0, 0).0, [255, 40, 255, 200]);
+  }
+
+  #[test]
+  fn test_encode_auto() {
+    let opaque = RgbaImage::from_fn(8, 8, |x, y| Rgba([x as u8 * 30, y as u8 * 30, 100, 255]));
+    let mut bytes = Vec::new();
+    assert_eq!(Dds::encode_auto(&mut bytes, &opaque).unwrap(), Compression::DXT1);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.compression, Compression::DXT1);
+    assert!(dds.is_opaque());
+
+    // A single translucent pixel is enough to need an alpha channel
+    let mut transparent = opaque.clone();
+    transparent.put_pixel(3, 5, Rgba([0, 0, 0, 100]));
+    let mut bytes = Vec::new();
+    assert_eq!(Dds::encode_auto(&mut bytes, &transparent).unwrap(), Compression::DXT5);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.compression, Compression::DXT5);
+    assert_eq!(dds.layers[0].get_pixel(3, 5).0[3], 100);
+  }
+
+  #[test]
+  fn test_texture_kind() {
+    let kind = |bytes: &[u8]| Header::decode(bytes).unwrap().texture_kind();
+    let masks = [0xFF0000, 0xFF00, 0xFF, 0xFF000000];
+    let texture = uncompressed_dds(4, 4, 32, masks, &[0; 64]);
+    assert_eq!(kind(&texture), TextureKind::Texture2D);
+
+    let mut cubemap = Vec::new();
+    Dds::encode_cubemap(&mut cubemap, &cubemap_faces(), Compression::None).unwrap();
+    assert_eq!(kind(&cubemap), TextureKind::Cubemap);
+
+    let volume = with_raw_header(&texture, |raw_header| {
+      raw_header.flags |= 0x800000;
+      raw_header.depth = 4;
+      raw_header.caps2 = 0x200000;
+    });
+    assert_eq!(kind(&volume), TextureKind::Volume);
+
+    // A depth without the volume cap still makes a volume
+    let volume = with_raw_header(&texture, |raw_header| {
+      raw_header.flags |= 0x800000;
+      raw_header.depth = 4;
+    });
+    let header = Header::decode(volume.as_slice()).unwrap();
+    assert_eq!((header.texture_kind(), header.depth), (TextureKind::Volume, 4));
+
+    let mut array = dx10_dds(4, 4, DxgiFormat::R8G8B8A8_UNORM, &[0; 128]);
+    array[140..144].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(kind(&array), TextureKind::Array);
+
+    // The DX10 resource dimension has the final say, whatever the depth
+    let mut volume = with_raw_header(&array, |raw_header| raw_header.depth = 2);
+    volume[140..144].copy_from_slice(&1u32.to_le_bytes());
+    assert_eq!(kind(&volume), TextureKind::Texture2D);
+    volume[132..136].copy_from_slice(&4u32.to_le_bytes());
+    let header = Header::decode(volume.as_slice()).unwrap();
+    assert_eq!((header.texture_kind(), header.depth), (TextureKind::Volume, 2));
+  }
+
+  #[test]
+  fn test_decode_normal_map() {
+    // Each BC5 block is filled with a single X and Y, with all indices pointing at the first endpoint
+    let block = |x: u8, y: u8| vec![x, x, 0, 0, 0, 0, 0, 0, y, y, 0, 0, 0, 0, 0, 0];
+    let data = [block(128, 128), block(218, 128), block(255, 255), block(40, 200)].concat();
+
+    for bytes in [four_cc_dds(16, 4, *b"ATI2", &data), dx10_dds(16, 4, DxgiFormat::BC5_UNORM, &data)].iter() {
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.layers[0].get_pixel(4, 0).0, [218, 128, 0, 255]);
+
+      let layers = Dds::decode_normal_map(bytes.as_slice()).unwrap();
+      // A flat normal points straight out of the surface
+      assert_eq!(layers[0].get_pixel(0, 0).0, [128, 128, 255, 255]);
+      assert_eq!(layers[0].get_pixel(5, 2).0, [218, 128, 217, 255]);
+      // X and Y are too long for a normal, so they're scaled down with Z left at 0
+      assert_eq!(layers[0].get_pixel(8, 3).0, [218, 218, 128, 255]);
+
+      for pixel in layers[0].pixels() {
+        let [x, y, z] = [0, 1, 2].map(|c| pixel.0[c] as f32 / 127.5 - 1.0);
+        assert!(((x * x + y * y + z * z).sqrt() - 1.0).abs() < 0.02, "{:?} is not a unit normal", pixel);
+      };
+    };
+  }
+
+  #[test]
+  fn test_decode_uncompressed_bit_depths() {
+    let cases = [
+      // L8
+      (8, [0xFF, 0, 0, 0], vec![0x5A], [0x5A, 0x5A, 0x5A, 255]),
+      // R5G6B5, with a pure green pixel
+      (16, [0xF800, 0x7E0, 0x1F, 0], vec![0xE0, 0x07], [0, 255, 0, 255]),
+      // R8G8B8, stored as B, G, R
+      (24, [0xFF0000, 0xFF00, 0xFF, 0], vec![0x30, 0x20, 0x10], [0x10, 0x20, 0x30, 255]),
+      // A8R8G8B8
+      (32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], vec![0x30, 0x20, 0x10, 0x80], [0x10, 0x20, 0x30, 0x80])
+    ];
+
+    for (bits, masks, pixel, expected) in cases.iter() {
+      // Two pixels in a row, to catch pixels being read at the wrong stride
+      let data = [pixel.as_slice(), pixel.as_slice()].concat();
+      let dds = Dds::decode(uncompressed_dds(2, 1, *bits, *masks, &data).as_slice()).unwrap();
+      assert_eq!(&dds.layers[0].get_pixel(0, 0).0, expected, "{} bits per pixel", bits);
+      assert_eq!(&dds.layers[0].get_pixel(1, 0).0, expected, "{} bits per pixel", bits);
+    };
+  }
+
+  #[test]
+  fn test_decode_preserve_x_bits() {
+    // X8R8G8B8 with garbage left in the X bits
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0], &[0x30, 0x20, 0x10, 0x7C]);
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0).0, [0x10, 0x20, 0x30, 255]);
+
+    let options = DecodeOptions { preserve_x_bits: true, ..Default::default() };
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0).0, [0x10, 0x20, 0x30, 0x7C]);
+
+    // X1R5G5B5 only has a single X bit
+    let bytes = uncompressed_dds(2, 1, 16, [0x7C00, 0x3E0, 0x1F, 0], &[0x1F, 0x80, 0x1F, 0x00]);
+    let dds = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+    assert_eq!(dds.layers[0].get_pixel(0, 0).0, [0, 0, 255, 255]);
+    assert_eq!(dds.layers[0].get_pixel(1, 0).0, [0, 0, 255, 0]);
+  }
+
+  #[test]
+  fn test_encode_partial_mipmaps() {
+    // A 256x256 texture could have 9 levels, but only the first 3 are given
+    let levels = [256, 128, 64].map(|size| RgbaImage::from_pixel(size, size, Rgba([size as u8, 0, 0, 255])));
+    let mut bytes = Vec::new();
+    assert!(matches!(
+      Dds::encode_with_mipmaps(&mut bytes, &levels, Compression::DXT1, &EncodeOptions::default()),
+      Err(EncodeError::InvalidMipmapCount { expected: 9, found: 3 })
+    ));
+
+    let options = EncodeOptions { allow_partial: true, ..Default::default() };
+    let mut bytes = Vec::new();
+    Dds::encode_with_mipmaps(&mut bytes, &levels, Compression::DXT1, &options).unwrap();
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.mipmap_count, 3);
+    assert_eq!(dds.layers.iter().map(|layer| layer.width()).collect::<Vec<_>>(), vec![256, 128, 64]);
+
+    // The levels given must still start from the main image and halve each time
+    let gapped = [levels[0].clone(), levels[2].clone()];
+    assert!(matches!(
+      Dds::encode_with_mipmaps(Vec::new(), &gapped, Compression::DXT1, &options),
+      Err(EncodeError::InvalidMipmapDimensions { level: 1, expected: (128, 128), found: (64, 64) })
+    ));
+    assert!(matches!(
+      Dds::encode_with_mipmaps(Vec::new(), &[], Compression::DXT1, &options),
+      Err(EncodeError::InvalidMipmapCount { found: 0, .. })
+    ));
+  }
+
+  #[test]
+  fn test_header_color_space() {
+    let color_space = |format: DxgiFormat| Header::decode(dx10_dds(4, 4, format, &[0; 8]).as_slice()).unwrap().color_space;
+    assert_eq!(color_space(DxgiFormat::BC1_UNORM_SRGB), ColorSpace::Srgb);
+    assert_eq!(color_space(DxgiFormat::BC1_UNORM), ColorSpace::Linear);
+    assert_eq!(color_space(DxgiFormat::BC1_TYPELESS), ColorSpace::Unknown);
+
+    // Legacy headers have nowhere to say
+    let header = Header::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    assert_eq!(header.color_space, ColorSpace::Unknown);
+  }
+
+  #[test]
+  fn test_raw_header_size() {
+    let raw_header = RawHeader::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    let mut bytes = Vec::new();
+    raw_header.encode(&mut bytes).unwrap();
+    // The magic bytes come first
+    assert_eq!(bytes.len(), 4 + RawHeader::SIZE);
+    assert_eq!(RawHeader::SIZE, 124);
+    assert_eq!(raw_header.to_bytes()[..], bytes[..]);
+
+    // Every field lands at the offset the specification gives it
+    let raw_header = RawHeader { height: 0x0102_0304, caps4: 0x0A0B_0C0D, ..raw_header };
+    let bytes = raw_header.to_bytes();
+    assert_eq!(bytes[12..16], [0x04, 0x03, 0x02, 0x01]);
+    assert_eq!(bytes[120..124], [0x0D, 0x0C, 0x0B, 0x0A]);
+    assert_eq!(RawHeader::decode(&bytes[..]).unwrap(), raw_header);
+
+    let raw_header_dx10 = RawHeaderDx10 { dxgi_format: 71, resource_dimension: 3, misc_flag: 0, array_size: 1, misc_flags2: 0 };
+    let mut bytes = Vec::new();
+    raw_header_dx10.encode(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), RawHeaderDx10::SIZE);
+    assert_eq!(RawHeaderDx10::decode(bytes.as_slice()).unwrap(), raw_header_dx10);
+  }
+
+  #[test]
+  fn test_dds_diff() {
+    let dds = Dds::decode(File::open("./samples/dxt1.dds").unwrap()).unwrap();
+    let diff = dds.diff(&dds);
+    assert!(diff.is_identical());
+    assert_eq!(diff.layers.len(), dds.layers.len());
+
+    // Nudging a single pixel of the second level only shows up there
+    let mut modified = dds.clone();
+    let pixel = modified.layers[1].get_pixel_mut(1, 0);
+    pixel.0[0] = if pixel.0[0] < 128 { pixel.0[0] + 10 } else { pixel.0[0] - 10 };
+    let diff = dds.diff(&modified);
+    assert!(!diff.is_identical());
+    assert!(diff.header_fields.is_empty());
+    assert!(diff.layers.iter().enumerate().all(|(level, layer)| layer.is_identical() == (level != 1)));
+    match diff.layers[1] {
+      LayerDiff::Compared { level: 1, max_error: 10, differing_pixels: 1, mean_error, psnr } => {
+        assert!(mean_error > 0.0 && psnr.is_finite());
+      },
+      ref layer => panic!("unexpected layer diff {:?}", layer)
+    };
+
+    // Dimensions and header fields are reported too
+    let mut resized = dds.clone();
+    resized.header.width *= 2;
+    resized.layers[0] = RgbaImage::new(dds.layers[0].width() * 2, dds.layers[0].height());
+    let diff = dds.diff(&resized);
+    assert_eq!(diff.header_fields, vec!["width"]);
+    assert!(matches!(diff.layers[0], LayerDiff::DimensionMismatch { level: 0, .. }));
+  }
+
+  #[test]
+  fn test_encode_rgb() {
+    let image = RgbaImage::from_fn(5, 3, |x, y| Rgba([x as u8 * 50, y as u8 * 80, 200, 255]));
+    let mut bytes = Vec::new();
+    Dds::encode_rgb(&mut bytes, &image).unwrap();
+    assert_eq!(bytes.len(), 128 + 5 * 3 * 3);
+
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, PixelFormat::R8G8B8);
+    assert_eq!((dds.header.bits_per_pixel, dds.header.pitch), (24, Some(15)));
+    assert_eq!(dds.layers[0], image);
+
+    // Alpha has nowhere to go, so it comes back opaque
+    let translucent = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 40]));
+    let mut bytes = Vec::new();
+    Dds::encode_rgb(&mut bytes, &translucent).unwrap();
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert!(dds.layers[0].pixels().all(|pixel| pixel.0 == [10, 20, 30, 255]));
+  }
+
+  #[test]
+  fn test_decode_detile() {
+    // A 16x8 DXT1 texture has two 2x2 tiles of blocks, each block a solid color of its own
+    let blocks = (0..8).map(|i| {
+      let color = (i as u16 * 4) << 11 | 0x1F;
+      let mut block = color.to_le_bytes().to_vec();
+      block.extend_from_slice(&color.to_le_bytes());
+      block.extend_from_slice(&[0; 4]);
+      block
+    }).collect::<Vec<_>>();
+    let linear = blocks.concat();
+
+    // Along the Z-order curve, each tile is stored in turn, its blocks going left to right, then top to bottom
+    let tiled = [0, 1, 4, 5, 2, 3, 6, 7].iter().map(|&i| blocks[i].clone()).collect::<Vec<_>>().concat();
+
+    let expected = Dds::decode(four_cc_dds(16, 8, *b"DXT1", &linear).as_slice()).unwrap();
+    let options = DecodeOptions { detile: Some(TileMode::Morton), ..Default::default() };
+    let dds = Dds::decode_with(four_cc_dds(16, 8, *b"DXT1", &tiled).as_slice(), &options).unwrap();
+    assert_eq!(dds.layers, expected.layers);
+
+    // Detiling needs blocks to shuffle around, and a power of two of them on each side
+    let bytes = uncompressed_dds(1, 1, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], &[0; 4]);
+    assert!(matches!(Dds::decode_with(bytes.as_slice(), &options), Err(DecodeError::UnsupportedTiling(TileMode::Morton))));
+    let bytes = four_cc_dds(12, 4, *b"DXT1", &[0; 24]);
+    assert!(matches!(Dds::decode_with(bytes.as_slice(), &options), Err(DecodeError::UnsupportedTiling(TileMode::Morton))));
+  }
+
+  #[test]
+  fn test_full_mipmap_count() {
+    assert_eq!(dds::full_mipmap_count(256, 256), 9);
+    assert_eq!(dds::full_mipmap_count(256, 1), 9);
+    assert_eq!(dds::full_mipmap_count(1, 256), 9);
+    assert_eq!(dds::full_mipmap_count(1, 1), 1);
+    assert_eq!(dds::full_mipmap_count(0, 0), 1);
+    // Odd sizes round down at each level, so 255 takes a level less than 256
+    assert_eq!(dds::full_mipmap_count(255, 100), 8);
+    assert_eq!(dds::full_mipmap_count(u32::MAX, 1), 32);
+  }
+
+  #[test]
+  fn test_decode_dxt5nm() {
+    // X is held by the alpha endpoints and Y by the green of the color endpoints, with every index at 0
+    let block = |x: u8, green: u16| {
+      let color = (green << 5).to_le_bytes();
+      vec![x, x, 0, 0, 0, 0, 0, 0, color[0], color[1], color[0], color[1], 0, 0, 0, 0]
+    };
+    let data = [block(128, 32), block(218, 32), block(128, 0), block(0, 63)].concat();
+
+    let plain = Dds::decode(four_cc_dds(16, 4, *b"DXT5", &data).as_slice()).unwrap();
+    assert_eq!(plain.layers[0].get_pixel(4, 0).0, [0, 129, 0, 218]);
+
+    let options = DecodeOptions { dxt5nm: true, ..DecodeOptions::default() };
+    for dds in [
+      Dds::decode_with(four_cc_dds(16, 4, *b"DXT5", &data).as_slice(), &options).unwrap(),
+      Dds::decode_with(dx10_dds(16, 4, DxgiFormat::BC3_UNORM, &data).as_slice(), &options).unwrap(),
+      Dds::decode(four_cc_dds(16, 4, *b"RXGB", &data).as_slice()).unwrap()
+    ].iter() {
+      let layer = &dds.layers[0];
+      // A flat normal points straight out of the surface
+      assert_eq!(layer.get_pixel(0, 0).0, [128, 129, 255, 255]);
+      assert_eq!(layer.get_pixel(5, 2).0, [218, 129, 217, 255]);
+      assert_eq!(layer.get_pixel(9, 1).0, [128, 0, 128, 255]);
+      // X and Y are too long for a normal, so they're scaled down with Z left at 0
+      assert_eq!(layer.get_pixel(15, 3).0, [37, 218, 128, 255]);
+    };
+
+    assert!(!Header::decode(four_cc_dds(16, 4, *b"RXGB", &data).as_slice()).unwrap().has_alpha());
+  }
+
+  #[test]
+  fn test_decode_cubemap_signaling() {
+    // Six 4x4 faces without mipmaps, each filled with its own gray level
+    let faces = |count: usize| (0..count).flat_map(|face| vec![face as u8 * 20; 64]).collect::<Vec<u8>>();
+    let masks = [0xFF, 0xFF00, 0xFF0000, 0xFF000000];
+    let dx10_cube = |count: usize, array_size: u32| {
+      let mut bytes = dx10_dds(4, 4, DxgiFormat::R8G8B8A8_UNORM, &faces(count));
+      bytes[136..140].copy_from_slice(&0x4u32.to_le_bytes());
+      bytes[140..144].copy_from_slice(&array_size.to_le_bytes());
+      bytes
+    };
+
+    let legacy = with_raw_header(&uncompressed_dds(4, 4, 32, masks, &faces(6)), |raw_header| {
+      raw_header.caps |= 0x8;
+      raw_header.caps2 = 0xFE00;
+    });
+
+    // texconv gives the number of cubemaps as the array size, while other writers count the faces
+    for bytes in [legacy, dx10_cube(6, 1), dx10_cube(6, 6)].iter() {
+      let header = Header::decode(bytes.as_slice()).unwrap();
+      assert_eq!(header.texture_kind(), TextureKind::Cubemap);
+      assert_eq!(header.array_size(), 1);
+
+      let cubemap = Cubemap::decode(bytes.as_slice()).unwrap();
+      for (face, layers) in cubemap.faces.iter().enumerate() {
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].get_pixel(3, 3).0, [face as u8 * 20; 4]);
+      };
+    };
+
+    for bytes in [dx10_cube(12, 2), dx10_cube(12, 12)].iter() {
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.header.array_size(), 2);
+      assert_eq!(dds.layers.len(), 12);
+      assert_eq!(dds.layers[11].get_pixel(0, 0).0, [220; 4]);
+    };
+  }
+
+  #[test]
+  fn test_decode_ignore_alpha() {
+    let options = DecodeOptions { ignore_alpha: true, ..DecodeOptions::default() };
+    for path in ["./samples/dxt5.dds", "./samples/qt/DXT3.dds", "./samples/qt/A8R8G8B8.dds"].iter() {
+      let bytes = std::fs::read(path).unwrap();
+      let plain = Dds::decode(bytes.as_slice()).unwrap();
+      let opaque = Dds::decode_with(bytes.as_slice(), &options).unwrap();
+      assert!(plain.layers[0].pixels().any(|pixel| pixel.0[3] != 255), "{} has no alpha to ignore", path);
+
+      // The colors are untouched, only the alpha is replaced
+      for (plain, opaque) in plain.layers.iter().zip(opaque.layers.iter()) {
+        assert!(opaque.pixels().all(|pixel| pixel.0[3] == 255));
+        for (a, b) in plain.pixels().zip(opaque.pixels()) {
+          assert_eq!(a.0[..3], b.0[..3]);
+        };
+      };
+    };
+  }
+
+  #[test]
+  fn test_serialize_header() {
+    let bytes = [
+      fs::read("./samples/dxt5.dds").unwrap(),
+      dx10_dds(4, 4, DxgiFormat::BC7_UNORM_SRGB, &[0; 16]),
+      four_cc_dds(4, 4, *b"ATI2", &[0; 16]),
+      four_cc_dds(4, 4, 113u32.to_le_bytes(), &[0; 128])
+    ];
+
+    for bytes in bytes.iter() {
+      let header = Header::decode(bytes.as_slice()).unwrap();
+      let json = serde_json::to_string(&header).unwrap();
+      assert_eq!(serde_json::from_str::<Header>(&json).unwrap(), header);
+    };
+
+    let header = Header::decode(bytes[0].as_slice()).unwrap();
+    let json = serde_json::to_value(&header).unwrap();
+    assert_eq!(json["compression"], "DXT5");
+    assert_eq!(json["pixel_format"], "Unknown");
+
+    // Unrecognized four-ccs keep their characters, while numeric D3DFORMAT values stay numbers
+    assert_eq!(serde_json::to_string(&Compression::Other(*b"ATI2")).unwrap(), "\"ATI2\"");
+    assert_eq!(serde_json::to_string(&Compression::Other(113u32.to_le_bytes())).unwrap(), "113");
+    assert_eq!(serde_json::from_str::<Compression>("\"DX1A\"").unwrap(), Compression::DXT1A);
+    assert!(serde_json::from_str::<Compression>("\"DXT10\"").is_err());
+
+    // Whole files carry their pixels along as base64
+    let dds = Dds::decode(bytes[0].as_slice()).unwrap();
+    let json = serde_json::to_string(&dds).unwrap();
+    assert_eq!(serde_json::from_str::<Dds>(&json).unwrap(), dds);
+  }
+
+  #[test]
+  fn test_decode_padded_block_rows() {
+    // Each DXT1 block is a single 5:6:5 color, with every index pointing at the first endpoint
+    let block = |color: u16| [color.to_le_bytes(), color.to_le_bytes(), [0; 2], [0; 2]].concat();
+    let rows = [[block(0xF800), block(0x07E0)].concat(), [block(0x001F), block(0xFFFF)].concat()];
+    let mipmap = block(0xF81F);
+
+    let tight = [rows[0].clone(), rows[1].clone(), mipmap.clone()].concat();
+    // Only the main image is padded, each 16 byte row of blocks out to a 24 byte pitch
+    let padded = [rows[0].clone(), vec![0xAB; 8], rows[1].clone(), vec![0xAB; 8], mipmap].concat();
+
+    let with_mipmaps = |bytes: &[u8], pitch: Option<u32>| with_raw_header(bytes, |raw_header| {
+      raw_header.mipmap_count = 2;
+      raw_header.flags |= 0x20000;
+      if let Some(pitch) = pitch {
+        raw_header.flags = raw_header.flags & !0x80000 | 0x8;
+        raw_header.pitch_or_linear_size = pitch;
+      };
+    });
+
+    let expected = Dds::decode(with_mipmaps(&four_cc_dds(8, 8, *b"DXT1", &tight), None).as_slice()).unwrap();
+    assert_eq!(expected.layers[0].get_pixel(7, 7).0, [255, 255, 255, 255]);
+
+    let bytes = with_mipmaps(&four_cc_dds(8, 8, *b"DXT1", &padded), Some(24));
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.layers, expected.layers);
+    assert_eq!(dds.header.layer_offsets().unwrap(), vec![(0, 48), (48, 8)]);
+
+    // A pitch covering every row of blocks is really the linear size, so nothing is skipped
+    let bytes = with_mipmaps(&four_cc_dds(8, 8, *b"DXT1", &tight), Some(32));
+    assert_eq!(Dds::decode(bytes.as_slice()).unwrap().layers, expected.layers);
+  }
+
+  #[test]
+  fn test_compression_to_dxgi() {
+    let pairs = [
+      (Compression::DXT1, DxgiFormat::BC1_UNORM),
+      (Compression::DXT3, DxgiFormat::BC2_UNORM),
+      (Compression::DXT5, DxgiFormat::BC3_UNORM),
+      (Compression::Other(*b"ATI1"), DxgiFormat::BC4_UNORM),
+      (Compression::Other(*b"ATI2"), DxgiFormat::BC5_UNORM),
+      (Compression::Other(*b"YUY2"), DxgiFormat::YUY2)
+    ];
+
+    for &(compression, format) in pairs.iter() {
+      assert_eq!(compression.to_dxgi(), Some(format));
+      assert_eq!(format.to_compression(), Some(compression));
+    };
+
+    // Aliases and premultiplied variants share a format, which maps back to the plain four-cc
+    assert_eq!(Compression::DXT1A.to_dxgi(), Some(DxgiFormat::BC1_UNORM));
+    assert_eq!(Compression::DXT4.to_dxgi(), Some(DxgiFormat::BC3_UNORM));
+    assert_eq!(Compression::Other(*b"BC5U").to_dxgi(), Some(DxgiFormat::BC5_UNORM));
+
+    assert_eq!(Compression::None.to_dxgi(), None);
+    assert_eq!(Compression::DX10.to_dxgi(), None);
+    assert_eq!(DxgiFormat::BC1_UNORM_SRGB.to_compression(), None);
+    assert_eq!(DxgiFormat::BC7_UNORM.to_compression(), None);
+
+    // Both signaling schemes decode the same payload identically
+    let data = fs::read("./samples/dxt5.dds").unwrap();
+    let legacy = Dds::decode(data.as_slice()).unwrap();
+    let format = legacy.header.compression.to_dxgi().unwrap();
+    let header_size = legacy.header.size();
+    let dx10 = Dds::decode(dx10_dds(4, 4, format, &data[header_size..]).as_slice()).unwrap();
+    assert_eq!(dx10.layers[0], legacy.layers[0]);
+  }
+
+  #[test]
+  fn test_decode_missing_channel_masks() {
+    let original = fs::read("./samples/qt/A8R8G8B8.dds").unwrap();
+    let bytes = with_raw_header(&original, |raw_header| {
+      raw_header.pixel_format.red_bit_mask = 0;
+      raw_header.pixel_format.green_bit_mask = 0;
+      raw_header.pixel_format.blue_bit_mask = 0;
+      raw_header.pixel_format.alpha_bit_mask = 0;
+    });
+
+    let expected = Dds::decode(original.as_slice()).unwrap();
+    let (dds, diagnostics) = Dds::decode_verbose(bytes.as_slice()).unwrap();
+    assert_eq!(dds.header.pixel_format, PixelFormat::A8R8G8B8);
+    assert_eq!(dds.layers, expected.layers);
+    assert_eq!(diagnostics, vec![Diagnostic::MissingChannelMasks]);
+
+    let options = DecodeOptions { strict: true, ..DecodeOptions::default() };
+    assert!(matches!(Dds::decode_with(bytes.as_slice(), &options), Err(DecodeError::MissingChannelMasks)));
+
+    // Other bit counts without masks are left alone
+    let bytes = uncompressed_dds(1, 1, 16, [0; 4], &[0x12, 0x34]);
+    assert_eq!(Header::decode(bytes.as_slice()).unwrap().pixel_format, PixelFormat::Unknown);
+  }
+
+  #[test]
+  fn test_decode_options_builder() {
+    assert_eq!(DecodeOptions::new(), DecodeOptions::default());
+
+    let options = DecodeOptions::new()
+      .ignore_alpha(true)
+      .color_key([255, 0, 255])
+      .swizzle([Channel::Alpha, Channel::Green, Channel::Zero, Channel::One])
+      .verify_blocks(50);
+    assert_eq!(options, DecodeOptions {
+      ignore_alpha: true,
+      color_key: Some([255, 0, 255]),
+      swizzle: [Channel::Alpha, Channel::Green, Channel::Zero, Channel::One],
+      verify_blocks: Some(50),
+      ..DecodeOptions::default()
+    });
+  }
+
+  #[test]
+  fn test_dominant_color() {
+    let color = Rgba([200, 100, 50, 255]);
+    let image = RgbaImage::from_pixel(16, 8, color);
+    let levels = [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)].iter()
+      .map(|&(width, height)| RgbaImage::from_pixel(width, height, color))
+      .collect::<Vec<RgbaImage>>();
+
+    let mut with_mipmaps = Vec::new();
+    Dds::encode_with_mipmaps(&mut with_mipmaps, &levels, Compression::None, &EncodeOptions::default()).unwrap();
+    let mut without_mipmaps = Vec::new();
+    Dds::encode(&mut without_mipmaps, &image, Compression::None).unwrap();
+
+    for bytes in [&with_mipmaps, &without_mipmaps].iter() {
+      assert_eq!(Dds::dominant_color(bytes.as_slice()).unwrap(), color.0);
+    };
+
+    // Only the smallest level is decoded, so the main image can be garbage
+    let mut bytes = with_mipmaps;
+    let header_size = Header::decode(bytes.as_slice()).unwrap().size();
+    bytes[header_size..header_size + 16 * 8 * 4].iter_mut().for_each(|b| *b = 0);
+    assert_eq!(Dds::dominant_color(bytes.as_slice()).unwrap(), color.0);
+
+    // Files without mipmaps average every pixel of the main image, rounding to the nearest value
+    let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+    image.put_pixel(1, 0, Rgba([255, 11, 0, 255]));
+    let mut bytes = Vec::new();
+    Dds::encode(&mut bytes, &image, Compression::None).unwrap();
+    assert_eq!(Dds::dominant_color(bytes.as_slice()).unwrap(), [128, 6, 0, 255]);
+  }
+
+  #[test]
+  fn test_creator_hint() {
+    let with_reserved = |words: &[(usize, [u8; 4])]| with_raw_header(&four_cc_dds(4, 4, *b"DXT1", &[0; 8]), |raw_header| {
+      for &(i, word) in words.iter() {
+        raw_header.reserved[i] = u32::from_le_bytes(word);
+      };
+    });
+
+    let cases = [
+      (with_reserved(&[(0, *b"NVTT"), (1, 0x20101u32.to_le_bytes())]), CreatorHint::Nvtt),
+      (with_reserved(&[(9, *b"NVTT"), (10, 0x20101u32.to_le_bytes())]), CreatorHint::Nvtt),
+      (with_reserved(&[(0, *b"TEXC"), (1, *b"ONV\0")]), CreatorHint::Texconv),
+      // Signatures only count in the fields their tools put them in
+      (with_reserved(&[(1, *b"NVTT")]), CreatorHint::Unknown),
+      (with_reserved(&[(0, *b"TEXC")]), CreatorHint::Unknown),
+      (with_reserved(&[(1, *b"TEXC"), (2, *b"ONV\0")]), CreatorHint::Unknown),
+      (with_reserved(&[(0, *b"GIMP"), (1, *b"-DDS")]), CreatorHint::Unknown),
+      (fs::read("./samples/dxt1.dds").unwrap(), CreatorHint::Unknown)
+    ];
+
+    for (bytes, expected) in cases.iter() {
+      let dds = Dds::decode(bytes.as_slice()).unwrap();
+      assert_eq!(dds.header.creator_hint(), *expected);
+
+      // Re-encoding keeps the signature
+      let mut encoded = Vec::new();
+      dds.encode_repaired(&mut encoded).unwrap();
+      assert_eq!(Header::decode(encoded.as_slice()).unwrap().creator_hint(), *expected);
+      assert_eq!(Header::decode(encoded.as_slice()).unwrap().reserved, dds.header.reserved);
+    };
+  }
+
+  #[test]
+  fn test_decode_payload_format_mismatch() {
+    // An 8x8 DXT1 surface takes 32 bytes, while A8R8G8B8 pixels of the same size take 256
+    for &pixel_bytes in [4, 3, 2].iter() {
+      let bytes = four_cc_dds(8, 8, *b"DXT1", &vec![0x7F; 64 * pixel_bytes]);
+      match Dds::decode(bytes.as_slice()) {
+        Err(DecodeError::PayloadFormatMismatch { compression: Compression::DXT1, expected: 32, found }) => {
+          assert_eq!(found, 64 * pixel_bytes);
+        },
+        result => panic!("expected a mismatch for {} byte pixels, got {:?}", pixel_bytes, result.map(|dds| dds.header))
+      };
+    };
+
+    // Block sized payloads decode as usual, even with some trailing bytes
+    let bytes = four_cc_dds(8, 8, *b"DXT1", &[0; 32]);
+    assert!(Dds::decode(bytes.as_slice()).is_ok());
+    let bytes = four_cc_dds(8, 8, *b"DXT1", &[0; 40]);
+    assert!(Dds::decode(bytes.as_slice()).is_ok());
+    let bytes = four_cc_dds(8, 8, *b"DXT1", &[0; 20]);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::InvalidBlockLength { .. })));
+  }
+
+  #[test]
+  fn test_mips_smallest_first() {
+    let levels = [(8, 4), (4, 2), (2, 1), (1, 1)].iter().enumerate()
+      .map(|(i, &(width, height))| RgbaImage::from_pixel(width, height, Rgba([i as u8 * 60, 0, 0, 255])))
+      .collect::<Vec<RgbaImage>>();
+
+    let mut bytes = Vec::new();
+    Dds::encode_with_mipmaps(&mut bytes, &levels, Compression::None, &EncodeOptions::default()).unwrap();
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    assert_eq!(dds.mips_smallest_first(), dds.layers.iter().rev().collect::<Vec<_>>());
+
+    // Chains given smallest first are written exactly like the same chain given largest first
+    let reversed = levels.iter().rev().cloned().collect::<Vec<RgbaImage>>();
+    let options = EncodeOptions { mip_order: MipOrder::SmallestFirst, ..EncodeOptions::default() };
+    let mut reversed_bytes = Vec::new();
+    Dds::encode_with_mipmaps(&mut reversed_bytes, &reversed, Compression::None, &options).unwrap();
+    assert_eq!(reversed_bytes, bytes);
+
+    // Read backwards, a largest first chain starts at 1x1, which can't have any more levels
+    assert!(matches!(
+      Dds::encode_with_mipmaps(&mut Vec::new(), &levels, Compression::None, &options),
+      Err(EncodeError::InvalidMipmapCount { expected: 1, found: 4 })
+    ));
+
+    // Each face of a cubemap keeps its place, with only its own chain reversed
+    let faces = cubemap_faces();
+    let mut bytes = Vec::new();
+    Dds::encode_cubemap(&mut bytes, &faces, Compression::None).unwrap();
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    let expected = faces.iter().flat_map(|face| face.iter().rev()).collect::<Vec<_>>();
+    assert_eq!(dds.mips_smallest_first(), expected);
+  }
+
+  #[test]
+  fn test_volume_payload_size() {
+    let bytes = volume_dds();
+    let header = Header::decode(bytes.as_slice()).unwrap();
+    let (offset, size) = *header.layer_offsets().unwrap().last().unwrap();
+    assert_eq!(offset + size, bytes.len() - header.size());
+    assert_eq!(header.layer_offsets().unwrap()[1], (8 * 8 * 8 * 4, 4 * 4 * 4 * 4));
+
+    // Each level decodes from its own front slice, rather than from the later slices of the level before it
+    let dds = Dds::decode(bytes.as_slice()).unwrap();
+    let front_slices = dds.layers.iter().map(|layer| layer.get_pixel(0, 0).0[0]).collect::<Vec<_>>();
+    assert_eq!(front_slices, vec![0, 8, 12, 14]);
+
+    let layout = Dds::describe(bytes.as_slice()).unwrap();
+    assert_eq!(layout.surfaces.len(), 15);
+    assert_eq!(layout.surfaces.iter().map(|surface| surface.size).sum::<usize>(), bytes.len() - header.size());
+
+    // The stream decoder waits for every slice of every level
+    let mut decoder = DdsStreamDecoder::new();
+    decoder.feed(&bytes[..bytes.len() - 1]);
+    assert!(decoder.try_finish().is_none());
+    decoder.feed(&bytes[bytes.len() - 1..]);
+    assert_eq!(decoder.try_finish().unwrap().unwrap(), dds);
+  }
+
+  #[test]
+  fn test_decode_truncated_uncompressed() {
+    let masks = [0xFF0000, 0xFF00, 0xFF, 0xFF000000];
+    let bytes = uncompressed_dds(4, 4, 32, masks, &[0; 60]);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::InvalidLayerLength { expected: 64, found: 60 })));
+    assert!(matches!(Dds::decode(&bytes[..128]), Err(DecodeError::InvalidLayerLength { expected: 64, found: 0 })));
+
+    // Formats with other pixel sizes go through the same path
+    let bytes = dx10_dds(4, 4, DxgiFormat::R16G16_FLOAT, &[0; 63]);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::InvalidLayerLength { expected: 64, found: 63 })));
+    assert!(matches!(Dds::decode_native(bytes.as_slice()), Err(DecodeError::InvalidLayerLength { .. })));
+
+    // Missing mipmaps fail too, unless they're skipped
+    let bytes = with_raw_header(&uncompressed_dds(4, 4, 32, masks, &[0; 64]), |raw_header| raw_header.mipmap_count = 3);
+    assert!(matches!(Dds::decode(bytes.as_slice()), Err(DecodeError::InvalidLayerLength { expected: 16, found: 0 })));
+    let options = DecodeOptions { max_level: Some(0), ..Default::default() };
+    assert_eq!(Dds::decode_with(bytes.as_slice(), &options).unwrap().layers.len(), 1);
   }
 }